@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+use raytracer::unstable::{cli, output};
+use raytracer::{AdaptiveSampling, Denoiser, Renderer};
+
+// Renders a scene without opening a window and writes the image to disk.
+fn main() {
+    let options = match cli::parse_render_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}\n", message);
+            }
+            eprintln!("{}", cli::RENDER_USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let scene = cli::load_scene(&options.scene, options.resolution).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    });
+
+    let mut renderer = Renderer::new(scene);
+    // The denoiser needs its guides even if they are not written.
+    let mut aovs = options.aovs.clone();
+    if options.denoise {
+        aovs.extend(Denoiser::GUIDES.into_iter().filter(|aov| !options.aovs.contains(aov)));
+    }
+    renderer.set_aovs(&aovs);
+    renderer.set_seed(options.seed);
+    renderer.set_sampler(options.sampler);
+    renderer.set_filter(options.filter);
+    renderer.set_adaptive_sampling(options.noise_threshold.map(AdaptiveSampling::new));
+    renderer.set_sample_target(options.frames * renderer.scene().camera.defaults.samples_per_pixel);
+    let start = Instant::now();
+
+    for frame in 1..=options.frames {
+        renderer.render_frame();
+        eprint!("\rframe {}/{}", frame, options.frames);
+
+        if renderer.is_converged() {
+            eprint!(", converged");
+            break;
+        }
+    }
+
+    let pixel_count = renderer.scene().camera.pixel_count();
+    eprintln!(" done in {:.2} s, {:.1} samples per pixel", start.elapsed().as_secs_f64(), renderer.sample_count() as f64 / pixel_count as f64);
+
+    let mut framebuffer = renderer.framebuffer();
+    if options.denoise {
+        framebuffer = Denoiser::default().denoise(&framebuffer).expect("the denoiser's guides are accumulated");
+        framebuffer.aovs.retain(|(aov, _)| options.aovs.contains(aov));
+    }
+
+    if let Err(error) = output::save_image(&options.output, &framebuffer, &options.display) {
+        eprintln!("error: failed to write {}: {}", options.output, error);
+        std::process::exit(1);
+    }
+}
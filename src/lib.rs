@@ -0,0 +1,55 @@
+// Path tracer based on the Ray Tracing in One Weekend books.
+//
+// Build a `Scene` from one of the built-in scenes, a scene file (`load_scene_file`) or your own
+// `HittableList` and `Camera`, then accumulate frames with a `Renderer` to get a `Framebuffer`.
+//
+// The items re-exported here are the public API. The modules behind them are internal and may be
+// reorganized at any time.
+
+mod library;
+
+pub use library::camera::{Camera, CameraDefaults};
+pub use library::color::Color;
+pub use library::hittable::Hittable;
+pub use library::hittable_list::HittableList;
+pub use library::material::Material;
+pub use library::renderer::{Framebuffer, Renderer};
+pub use library::scene::Scene;
+pub use library::texture::Texture;
+
+// Shapes and transforms wrapped by the `Hittable` variants.
+pub use library::constant_medium::ConstantMedium;
+pub use library::mesh::{Face, MeshData, TriangleMesh};
+pub use library::quad::Quad;
+pub use library::quadbox::Quadbox;
+pub use library::rotate_y::RotateY;
+pub use library::sphere::Sphere;
+pub use library::translate::Translate;
+pub use library::triangle::Triangle;
+
+// Images and noise wrapped by the `Texture` variants.
+pub use library::image::{ColorSpace, Image, ImageCache};
+pub use library::image_texture::{ImageTexture, TextureFilter, UvTransform, WrapMode};
+pub use library::perlin::Perlin;
+
+// Scene and mesh files.
+pub use library::obj::{load_obj, ObjError};
+pub use library::scene_file::{load as load_scene_file, SceneFileError};
+
+// Render settings and post-processing.
+pub use library::aov::Aov;
+pub use library::denoise::Denoiser;
+pub use library::film::AdaptiveSampling;
+pub use library::filter::{Filter, FilterKind};
+pub use library::sampler::Sampler;
+pub use library::tone_mapping::{DisplayTransform, ToneMapping};
+
+// Command line handling and window input shared by the `raytracer` and `raytracer-render` binaries.
+// Not part of the public API, anything in here may change without notice.
+#[doc(hidden)]
+pub mod unstable {
+    pub use crate::library::{cli, output};
+
+    #[cfg(feature = "viewer")]
+    pub use crate::library::{check_input, fps_counter};
+}
@@ -30,42 +30,66 @@ impl Aabb {
         }
     }
 
+    pub fn empty() -> Self {
+        Aabb::new(Interval::empty(), Interval::empty(), Interval::empty())
+    }
+
     pub fn hit(&self, ray: Ray, ray_t: Interval) -> Option<Interval> {
-        let mut t_result: Option<Interval> = None;
+        // Narrow the interval slab by slab, the ray only hits the box if it overlaps all three.
+        let mut min = ray_t.min;
+        let mut max = ray_t.max;
 
         for a in 0..3 {
             let index = a as usize;
 
-            if let (Some(origin), Some(direction)) = (ray.origin.get(index), ray.direction.get(index)) {
-                let inverse_direction = 1.0 / direction;
-
-                let mut t0 = (self.axis(a).min - origin) * inverse_direction;
-                let mut t1 = (self.axis(a).max - origin) * inverse_direction;
-
-                if inverse_direction < 0.0 {
-                    (t0, t1) = (t1, t0);
-                }
+            let inverse_direction = 1.0 / ray.direction[index];
+            let origin = ray.origin[index];
 
-                let mut min = ray_t.min;
-                let mut max = ray_t.max;
+            let mut t0 = (self.axis(a).min - origin) * inverse_direction;
+            let mut t1 = (self.axis(a).max - origin) * inverse_direction;
 
-                if t0 > min {
-                    min = t0;
-                }
+            if inverse_direction < 0.0 {
+                (t0, t1) = (t1, t0);
+            }
 
-                if t1 < max {
-                    max = t1;
-                }
-                
-                if max <= min {
-                    return None;
-                }
+            if t0 > min {
+                min = t0;
+            }
 
-                t_result = Some(Interval::new(min, max));
+            if t1 < max {
+                max = t1;
+            }
+            
+            if max <= min {
+                return None;
             }
         }
 
-        t_result
+        Some(Interval::new(min, max))
+    }
+
+    pub fn centroid(&self) -> Vector3<f64> {
+        Vector3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size().max(0.0), self.y.size().max(0.0), self.z.size().max(0.0));
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    // Returns the index of the axis along which the box is the widest.
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() >= self.y.size() && self.x.size() >= self.z.size() {
+            0
+        } else if self.y.size() >= self.z.size() {
+            1
+        } else {
+            2
+        }
     }
 
     pub fn axis(&self, n: i32) -> Interval {
@@ -0,0 +1,127 @@
+use crate::library::{color::Color, hit_object::HitObject, ray::Ray, scene::Scene};
+
+// Arbitrary output variables: auxiliary images taken from the first surface the camera rays hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    Albedo,
+    // World space shading normal, facing the camera.
+    Normal,
+    // Distance from the camera, zero where nothing was hit.
+    Depth,
+    // World space position, zero where nothing was hit.
+    Position,
+    // Index of the top-level scene object plus one, zero where nothing was hit.
+    ObjectId,
+    // Index of the material plus one, counting materials in the order the scene's objects first
+    // use them, zero where nothing was hit.
+    MaterialId,
+    // Light emitted towards the camera by lights and the background, without any scattering.
+    Emission,
+}
+
+const AOV_COUNT: usize = 7;
+
+impl Aov {
+    pub const ALL: [Aov; AOV_COUNT] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId, Aov::MaterialId, Aov::Emission];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Emission => "emission",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Self::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    // Whether the variable holds an index rather than a quantity that can be averaged.
+    pub fn is_id(self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    // Channel names used in multi-channel images. Single channel variables store their value in
+    // all three components of their colors.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::Normal => &["normal.X", "normal.Y", "normal.Z"],
+            Aov::Depth => &["depth.Z"],
+            Aov::Position => &["position.X", "position.Y", "position.Z"],
+            Aov::ObjectId => &["object_id.id"],
+            Aov::MaterialId => &["material_id.id"],
+            Aov::Emission => &["emission.R", "emission.G", "emission.B"],
+        }
+    }
+}
+
+// All output variables of one pixel, summed over its samples. The ids are taken from the latest
+// sample instead, since averaging indices is meaningless.
+#[derive(Debug, Clone, Copy)]
+pub struct AovPixel {
+    values: [Color; AOV_COUNT],
+}
+
+impl Default for AovPixel {
+    fn default() -> Self {
+        AovPixel { values: [Color::new(0.0, 0.0, 0.0); AOV_COUNT] }
+    }
+}
+
+impl AovPixel {
+    // Values of a camera ray at the first surface it hits.
+    pub fn from_hit(ray: Ray, hit_object: &HitObject) -> Self {
+        let depth = hit_object.t * ray.direction.norm();
+        let object_id = (hit_object.object_id + 1) as f64;
+        let material_id = (hit_object.material_id + 1) as f64;
+        let (normal, point) = (hit_object.normal, hit_object.point);
+
+        AovPixel {
+            values: [
+                hit_object.material.albedo(hit_object),
+                Color::new(normal.x, normal.y, normal.z),
+                Color::new(depth, depth, depth),
+                Color::new(point.x, point.y, point.z),
+                Color::new(object_id, object_id, object_id),
+                Color::new(material_id, material_id, material_id),
+                hit_object.material.emitted(hit_object),
+            ],
+        }
+    }
+
+    // Values of a camera ray that leaves the scene, which only sees the background.
+    pub fn from_miss(scene: &Scene) -> Self {
+        let mut pixel = AovPixel::default();
+        pixel.values[Aov::Emission as usize] = scene.camera.defaults.background;
+        pixel
+    }
+
+    pub fn get(&self, aov: Aov) -> Color {
+        self.values[aov as usize]
+    }
+
+    // Adds the values of another sample.
+    pub fn add_sample(&mut self, other: &AovPixel) {
+        for aov in Aov::ALL {
+            if aov.is_id() {
+                self.values[aov as usize] = other.values[aov as usize];
+            } else {
+                self.values[aov as usize] += other.values[aov as usize];
+            }
+        }
+    }
+
+    // The value of the variable averaged over `sample_count` samples.
+    pub fn average(&self, aov: Aov, sample_count: usize) -> Color {
+        if aov.is_id() {
+            return self.get(aov);
+        }
+
+        self.get(aov) * (1.0 / sample_count.max(1) as f64)
+    }
+}
@@ -1,111 +1,180 @@
-// use std::{cmp::Ordering, sync::Arc};
-// use rand::prelude::*;
-
-// use crate::{aabb::Aabb, hit_object::HitObject, hittable::{Hittable, HittableTrait}, hittable_list::HittableList, interval::Interval, ray::Ray};
-
-// #[derive(Debug, Clone)]
-// pub struct BvhNode {
-//     left: Hittable,
-//     right: Hittable,
-//     bounding_box: Aabb,
-// }
-
-// impl BvhNode {
-//     pub fn new(src_objects: &Vec<Hittable>, start: usize, end: usize) -> Self {
-//         let mut objects: Vec<Hittable> = src_objects.iter().cloned().collect();
-
-//         let axis = Self::random_int(0..3);
-
-//         let comparator = match axis {
-//             0 => Self::box_x_compare,
-//             1 => Self::box_y_compare,
-//             _ => Self::box_z_compare,
-//         };
-
-//         let object_span = end - start;
-
-//         let left;
-//         let right;
-
-//         if object_span == 1 {
-//             left = &objects[start];
-//             right = &objects[start];
-//         } else if object_span == 2 {
-//             if comparator(&objects[start], &objects[start + 1]) == Ordering::Less {
-//                 left = &objects[start];
-//                 right = &objects[start + 1];
-//             } else {
-//                 left = &objects[start + 1];
-//                 right = &objects[start];
-//             }
-//         } else {
-//             objects[start..end].sort_by(|a, b| comparator(a, b));
-
-//             let mid = start + object_span / 2;
-//             left = &Hittable::Bvh(Self::new(&objects, start, mid));
-//             right = &Hittable::Bvh(Self::new(&objects, mid, end));
-//         }
-
-//         let bounding_box = Aabb::new_from_box(left.get_bounding_box(), right.get_bounding_box());
-
-//         BvhNode { left, right, bounding_box }
-//     }
-
-//     pub fn new_from_list(list: HittableList) -> Self {
-//         let length = list.objects.len();
-//         Self::new(&list.objects, 0, length)
-//     }
-
-//     fn random_int(range: std::ops::Range<i32>) -> i32 {
-//         let mut rng = rand::thread_rng();
-//         rng.gen_range(range)
-//     }
-
-//     fn box_compare(a: &Hittable, b: &Hittable, axis_index: i32) -> std::cmp::Ordering {
-//         let a_min = a.get_bounding_box().axis(axis_index).min;
-//         let b_min = b.get_bounding_box().axis(axis_index).min;
-
-//         if a_min < b_min {
-//             Ordering::Less
-//         } else if a_min > b_min {
-//             Ordering::Greater
-//         } else {
-//             Ordering::Equal
-//         }
-//     }
-
-//     fn box_x_compare(a: &Hittable, b: &Hittable) -> std::cmp::Ordering {
-//         Self::box_compare(a, b, 0)
-//     }
-
-//     fn box_y_compare(a: &Hittable, b: &Hittable) -> std::cmp::Ordering {
-//         Self::box_compare(a, b, 1)
-//     }
-
-//     fn box_z_compare(a: &Hittable, b: &Hittable) -> std::cmp::Ordering {
-//         Self::box_compare(a, b, 2)
-//     }
-// }
-
-// impl HittableTrait for BvhNode {
-//     fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
-//         if self.bounding_box.hit(ray, ray_t).is_none() {
-//             return None;
-//         }
-
-//         let mut hit_object = self.left.hit(ray, ray_t);
-
-//         let mut max = ray_t.max;
-//         if let Some(object) = hit_object {
-//             max = object.t;
-//         }
-
-//         hit_object = self.right.hit(ray, Interval::new(ray_t.min, max));
-
-//         hit_object
-//     }
-
-//     fn get_bounding_box(&self) -> Aabb {
-//         self.bounding_box
-//     }
-// }
+use nalgebra::Vector3;
+
+use crate::library::basic_lib::*;
+use crate::library::{hittable::{Hittable, HittableTrait}, hittable_list::HittableList};
+
+// Number of buckets the centroids are binned into when evaluating the surface area heuristic.
+const SAH_BUCKETS: usize = 12;
+// Estimated cost of traversing a node relative to intersecting a primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+
+#[derive(Debug, Clone)]
+pub struct BvhNode {
+    left: Hittable,
+    right: Option<Hittable>,
+    // Index of each child in the list the hierarchy was built from, None for the nodes added while building it.
+    object_ids: [Option<usize>; 2],
+    axis: usize,
+    bounding_box: Aabb,
+}
+
+impl BvhNode {
+    // Hits are tagged with the index of the object in `objects` they belong to.
+    pub fn new(objects: Vec<Hittable>) -> Self {
+        Self::build(objects.into_iter().enumerate().collect())
+    }
+
+    fn build(mut objects: Vec<(usize, Hittable)>) -> Self {
+        assert!(!objects.is_empty(), "cannot build a BVH without objects");
+
+        let bounding_box = objects.iter().fold(Aabb::empty(), |acc, (_, object)| Aabb::new_from_box(acc, object.get_bounding_box()));
+
+        if objects.len() == 1 {
+            let (id, left) = objects.pop().unwrap();
+            return BvhNode { left, right: None, object_ids: [Some(id), None], axis: 0, bounding_box };
+        }
+
+        let (axis, right_objects) = sah_split(&mut objects, |(_, object)| object.get_bounding_box());
+
+        let (left_id, left) = Self::into_child(objects);
+        let (right_id, right) = Self::into_child(right_objects);
+
+        BvhNode { left, right: Some(right), object_ids: [left_id, right_id], axis, bounding_box }
+    }
+
+    pub fn new_from_list(list: HittableList) -> Self {
+        Self::new(list.objects)
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &Hittable> {
+        std::iter::once(&self.left).chain(self.right.as_ref())
+    }
+
+    pub fn children_mut(&mut self) -> impl Iterator<Item = &mut Hittable> {
+        std::iter::once(&mut self.left).chain(self.right.as_mut())
+    }
+
+    fn into_child(mut objects: Vec<(usize, Hittable)>) -> (Option<usize>, Hittable) {
+        if objects.len() == 1 {
+            let (id, object) = objects.pop().unwrap();
+            (Some(id), object)
+        } else {
+            (None, Hittable::Bvh(Box::new(Self::build(objects))))
+        }
+    }
+
+    fn hit_child(child: &Hittable, object_id: Option<usize>, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
+        let mut hit_object = child.hit(ray, ray_t)?;
+        if let Some(object_id) = object_id {
+            hit_object.object_id = object_id;
+        }
+
+        Some(hit_object)
+    }
+}
+
+impl HittableTrait for BvhNode {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
+        self.bounding_box.hit(ray, ray_t)?;
+
+        let [left_id, right_id] = self.object_ids;
+        let Some(right) = &self.right else {
+            return Self::hit_child(&self.left, left_id, ray, ray_t);
+        };
+
+        // Visit the child closest to the ray origin first so the far child can be culled sooner.
+        let ((first, first_id), (second, second_id)) = if ray.direction[self.axis] < 0.0 {
+            ((right, right_id), (&self.left, left_id))
+        } else {
+            ((&self.left, left_id), (right, right_id))
+        };
+
+        let first_hit = Self::hit_child(first, first_id, ray, ray_t);
+
+        let mut max = ray_t.max;
+        if let Some(object) = &first_hit {
+            max = object.t;
+        }
+
+        Self::hit_child(second, second_id, ray, Interval::new(ray_t.min, max)).or(first_hit)
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        self.bounding_box
+    }
+}
+
+// Partitions the objects with the surface area heuristic, leaving the left half in `objects`
+// and returning the split axis together with the right half.
+pub fn sah_split<T, F: Fn(&T) -> Aabb>(objects: &mut Vec<T>, bounding_box_of: F) -> (usize, Vec<T>) {
+    let centroids: Vec<_> = objects.iter().map(|object| bounding_box_of(object).centroid()).collect();
+    let centroid_box = centroids.iter().fold(Aabb::empty(), |acc, centroid| Aabb::new_from_box(acc, Aabb::new_from_point(*centroid, *centroid)));
+
+    let axis = centroid_box.longest_axis();
+    let extent = centroid_box.axis(axis as i32);
+
+    // All centroids coincide, so no plane can separate them; split by count instead.
+    if extent.size() <= 0.0 || !extent.size().is_finite() {
+        let right = objects.split_off(objects.len() / 2);
+        return (axis, right);
+    }
+
+    let bucket_of = |centroid: &Vector3<f64>| {
+        let offset = (centroid[axis] - extent.min) / extent.size();
+        ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+    };
+
+    let mut bucket_counts = [0usize; SAH_BUCKETS];
+    let mut bucket_boxes = [Aabb::empty(); SAH_BUCKETS];
+    for (object, centroid) in objects.iter().zip(centroids.iter()) {
+        let bucket = bucket_of(centroid);
+        bucket_counts[bucket] += 1;
+        bucket_boxes[bucket] = Aabb::new_from_box(bucket_boxes[bucket], bounding_box_of(object));
+    }
+
+    // Sweep from the right so the cost of every split plane is known in a single pass from the left.
+    let mut right_areas = [0.0; SAH_BUCKETS];
+    let mut right_counts = [0usize; SAH_BUCKETS];
+    let mut right_box = Aabb::empty();
+    let mut right_count = 0;
+    for bucket in (1..SAH_BUCKETS).rev() {
+        right_box = Aabb::new_from_box(right_box, bucket_boxes[bucket]);
+        right_count += bucket_counts[bucket];
+        right_areas[bucket] = right_box.surface_area();
+        right_counts[bucket] = right_count;
+    }
+
+    let mut best_split = SAH_BUCKETS / 2;
+    let mut best_cost = f64::INFINITY;
+    let mut left_box = Aabb::empty();
+    let mut left_count = 0;
+    for split in 1..SAH_BUCKETS {
+        left_box = Aabb::new_from_box(left_box, bucket_boxes[split - 1]);
+        left_count += bucket_counts[split - 1];
+
+        if left_count == 0 || right_counts[split] == 0 {
+            continue;
+        }
+
+        let cost = TRAVERSAL_COST + left_box.surface_area() * left_count as f64 + right_areas[split] * right_counts[split] as f64;
+        if cost < best_cost {
+            best_cost = cost;
+            best_split = split;
+        }
+    }
+
+    let (left, right): (Vec<_>, Vec<_>) = objects
+        .drain(..)
+        .zip(centroids.iter())
+        .partition(|(_, centroid)| bucket_of(centroid) < best_split);
+
+    // Every centroid landed in the same bucket, fall back to an even split.
+    if left.is_empty() || right.is_empty() {
+        objects.extend(left.into_iter().chain(right).map(|(object, _)| object));
+        let right = objects.split_off(objects.len() / 2);
+        return (axis, right);
+    }
+
+    objects.extend(left.into_iter().map(|(object, _)| object));
+    (axis, right.into_iter().map(|(object, _)| object).collect())
+}
@@ -1,13 +1,13 @@
 use std::f64::consts::PI;
-use rand::prelude::*;
 use nalgebra::Vector3;
 
-use crate::library::constants::{WIDTH, HEIGHT};
-use crate::library::{ray::Ray, scene::Scene, material::Material, color::Color};
+use crate::library::{ray::{Ray, RayDifferentials}, scene::Scene, color::Color, random};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub defaults: CameraDefaults,
+    pub image_width: usize,
+    pub image_height: usize,
     position: Vector3<f64>,
 
     pixel00_loc: Vector3<f64>,
@@ -21,11 +21,15 @@ impl Camera {
     pub fn init(default: CameraDefaults) -> Self {
         let position = default.lookfrom;
 
+        // Calculate the image height, and ensure that it's at least 1.
+        let image_width = default.image_width.max(1);
+        let image_height = ((image_width as f64 / default.aspect_ratio).round() as usize).max(1);
+
         // Determine viewport dimensions.
         let theta = Self::degrees_to_radians(default.vertical_fov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h * default.focus_distance;
-        let viewport_width: f64 = viewport_height * (WIDTH as f64 / HEIGHT as f64);
+        let viewport_width: f64 = viewport_height * (image_width as f64 / image_height as f64);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
         let w = (default.lookfrom - default.lookat).normalize();
@@ -37,8 +41,8 @@ impl Camera {
         let viewport_v = viewport_height * -v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        let pixel_delta_u = viewport_u / WIDTH as f64;
-        let pixel_delta_v = viewport_v / HEIGHT as f64;
+        let pixel_delta_u = viewport_u / image_width as f64;
+        let pixel_delta_v = viewport_v / image_height as f64;
 
         // Calculate the location of the upper left pixel.
         let viewport_upper_left = position - (default.focus_distance * w) - viewport_u / 2.0 - viewport_v / 2.0;
@@ -49,14 +53,17 @@ impl Camera {
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Camera::new(default, position, pixel00_loc, pixel_delta_u, pixel_delta_v, defocus_disk_u, defocus_disk_v)
+        Camera::new(default, image_width, image_height, position, pixel00_loc, pixel_delta_u, pixel_delta_v, defocus_disk_u, defocus_disk_v)
     }
 
-    pub fn new(defaults: CameraDefaults, position: Vector3<f64>, pixel00_loc: Vector3<f64>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(defaults: CameraDefaults, image_width: usize, image_height: usize, position: Vector3<f64>, pixel00_loc: Vector3<f64>,
         pixel_delta_u: Vector3<f64>, pixel_delta_v: Vector3<f64>, defocus_disk_u: Vector3<f64>, defocus_disk_v: Vector3<f64>) -> Self {
         Camera {
             position,
             defaults,
+            image_width,
+            image_height,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
@@ -69,45 +76,51 @@ impl Camera {
         scene.camera = Camera::init(self.defaults);
     }
 
-    // Get a randomly-sampled camera ray for the pixel at location i,j
-    // originating from the camera defocus disk.
-    pub fn get_ray(&self, i: usize, j: usize) -> Ray {
+    // Number of pixels in the rendered image.
+    pub fn pixel_count(&self) -> usize {
+        self.image_width * self.image_height
+    }
+
+    // Get a randomly-sampled camera ray through the pixel at location i,j, offset from its center by
+    // the given fraction of a pixel and originating from the camera defocus disk. Its differentials
+    // start at the same point of the lens and are offset by `differential_scale` pixels, the share of
+    // the pixel this sample stands for.
+    pub fn get_ray(&self, i: usize, j: usize, (offset_x, offset_y): (f64, f64), differential_scale: f64) -> Ray {
         let delta_u = self.pixel_delta_u;
         let delta_v = self.pixel_delta_v;
         let pixel_center = self.pixel00_loc + (i as f64 * delta_u) + (j as f64 * delta_v);   
 
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_sample = pixel_center + (offset_x * delta_u) + (offset_y * delta_v);
 
         let ray_origin = self.defocus_disk_sample();
         let ray_direction = pixel_sample - ray_origin;
-        let ray_time = Material::random_float();
+        let ray_time = random::sample_1d();
+
+        let differentials = RayDifferentials {
+            x_origin: ray_origin,
+            x_direction: ray_direction + delta_u * differential_scale,
+            y_origin: ray_origin,
+            y_direction: ray_direction + delta_v * differential_scale,
+        };
 
-        Ray::new(ray_origin, ray_direction, ray_time)
+        Ray::new(ray_origin, ray_direction, ray_time).with_differentials(differentials)
     }
     
     pub fn degrees_to_radians(degrees: f64) -> f64 {
         degrees * PI / 180.0
     }
 
-    // Returns a random point in the square surrounding a pixel at the origin.
-    fn pixel_sample_square(&self) -> Vector3<f64> {
-        let mut rng = rand::thread_rng();
-
-        let px = -0.5 + rng.gen::<f64>();
-        let py = -0.5 + rng.gen::<f64>();
-
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
-    }
-
     // Returns a random point in the camera defocus disk.
     fn defocus_disk_sample(&self) -> Vector3<f64> {
-        let p = Ray::random_in_unit_disk();
+        let p = Ray::sample_unit_disk(random::sample_2d());
         self.position + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct CameraDefaults {
+    pub image_width: usize,
+    pub aspect_ratio: f64,
     pub samples_per_pixel: usize,
     pub max_depth: usize,
     pub background: Color,
@@ -8,7 +8,7 @@ pub fn check_input(window: &Window, scene: &mut Scene) -> bool {
     turn_camera(&pressed_keys, scene, speed) || move_camera(&pressed_keys, scene, speed)
 }
 
-fn turn_camera(pressed_keys: &Vec<Key>, scene: &mut Scene, speed: f64) -> bool {
+fn turn_camera(pressed_keys: &[Key], scene: &mut Scene, speed: f64) -> bool {
     let mut camera = scene.camera;
 
     let lookfrom = &mut camera.defaults.lookfrom;
@@ -19,7 +19,7 @@ fn turn_camera(pressed_keys: &Vec<Key>, scene: &mut Scene, speed: f64) -> bool {
     for key in pressed_keys {
         
         let forward = (*lookat - *lookfrom).normalize();
-        let right = lookfrom.cross(&vup).normalize();
+        let right = lookfrom.cross(vup).normalize();
         
         is_key_pressed = true;
         match key {
@@ -38,7 +38,7 @@ fn turn_camera(pressed_keys: &Vec<Key>, scene: &mut Scene, speed: f64) -> bool {
     is_key_pressed
 }
 
-fn move_camera(pressed_keys: &Vec<Key>, scene: &mut Scene, speed: f64) -> bool {
+fn move_camera(pressed_keys: &[Key], scene: &mut Scene, speed: f64) -> bool {
     let mut camera = scene.camera;
 
     let lookfrom = &mut camera.defaults.lookfrom;
@@ -49,7 +49,7 @@ fn move_camera(pressed_keys: &Vec<Key>, scene: &mut Scene, speed: f64) -> bool {
     for key in pressed_keys {
         
         let forward = (*lookat - *lookfrom).normalize();
-        let right = lookfrom.cross(&vup).normalize();
+        let right = lookfrom.cross(vup).normalize();
         
         is_key_pressed = true;
         match key {
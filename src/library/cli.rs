@@ -0,0 +1,326 @@
+use crate::library::{aov::Aov, filter::{Filter, FilterKind}, random, sampler::Sampler, scene::{Scene, DEFAULT_SCENE}, scene_file, tone_mapping::{DisplayTransform, ToneMapping}};
+
+pub const VIEW_USAGE: &str = "\
+Usage:
+    raytracer [--scene <index> | --scene-file <file.toml>] [resolution options] [--denoise] [--seed <n>] [--sampler <name>] [--filter <name>]
+              [--exposure <ev>] [--tonemap <name>]
+
+Options:
+    --scene <index>     Built-in scene to load (default: 10)
+    --scene-file <file> Scene description file to load instead of a built-in scene
+    --width <pixels>    Image width, overrides the scene's setting
+    --height <pixels>   Image height, overrides the scene's setting
+    --aspect-ratio <r>  Width to height ratio as a number or `w:h` (e.g. 16:9), used with only one of width and height
+    --denoise           Start with the denoiser enabled, toggle it with N
+    --seed <n>          Seed of the random samples (default: 0)
+    --sampler <name>    Sample sequence: independent, stratified, halton or sobol (default: sobol)
+    --filter <name>     Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos (default: box)
+    --filter-radius <r> Filter radius in pixels (default: 0.5 box, 1 tent, 1.5 gaussian, 2 mitchell, 3 lanczos)
+    --exposure <ev>     Exposure in stops, change it with - and = (default: 0)
+    --tonemap <name>    Tone mapping: none, reinhard, extended-reinhard, aces or agx, cycle with T (default: none)";
+
+pub const RENDER_USAGE: &str = "\
+Usage:
+    raytracer-render [--scene <index> | --scene-file <file.toml>] [resolution options] [--frames <count>] [--output <file.png>] [--aov <list>] [--denoise] [--noise-threshold <t>] [--seed <n>] [--sampler <name>] [--filter <name>]
+              [--exposure <ev>] [--tonemap <name>]
+
+Options:
+    --scene <index>     Built-in scene to load (default: 10)
+    --scene-file <file> Scene description file to load instead of a built-in scene
+    --width <pixels>    Image width, overrides the scene's setting
+    --height <pixels>   Image height, overrides the scene's setting
+    --aspect-ratio <r>  Width to height ratio as a number or `w:h` (e.g. 16:9), used with only one of width and height
+    --frames <count>    Number of frames to accumulate (default: 100), the maximum with --noise-threshold
+    --output <file>     Path of the image to write (default: render.png)
+    --aov <list>        Comma separated output variables to write next to the image, or as extra channels of an .exr file:
+                        albedo, normal, depth, position, object_id, material_id, emission
+    --denoise           Denoise the image using the albedo, normal, depth and emission output variables
+    --noise-threshold <t>
+                        Sample adaptively until every pixel's relative standard error is below t (e.g. 0.01)
+    --seed <n>          Seed of the random samples, the same seed gives the same image (default: 0)
+    --sampler <name>    Sample sequence: independent, stratified, halton or sobol (default: sobol)
+    --filter <name>     Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos (default: box)
+    --filter-radius <r> Filter radius in pixels (default: 0.5 box, 1 tent, 1.5 gaussian, 2 mitchell, 3 lanczos)
+    --exposure <ev>     Exposure in stops (default: 0)
+    --tonemap <name>    Tone mapping: none, reinhard, extended-reinhard, aces or agx (default: none)";
+
+#[derive(Debug, Clone)]
+pub enum SceneSource {
+    Builtin(usize),
+    File(String),
+}
+
+impl SceneSource {
+    pub fn load(&self) -> Result<Scene, String> {
+        match self {
+            SceneSource::Builtin(index) => Ok(Scene::new(*index)),
+            SceneSource::File(path) => scene_file::load(path).map_err(|error| error.to_string()),
+        }
+    }
+}
+
+// Image size requested on the command line, unset values are taken from the scene.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Resolution {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub aspect_ratio: Option<f64>,
+}
+
+impl Resolution {
+    pub fn apply(&self, scene: &mut Scene) {
+        let defaults = scene.camera.defaults;
+        let aspect_ratio = self.aspect_ratio.unwrap_or(defaults.aspect_ratio);
+
+        let (width, aspect_ratio) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, width as f64 / height as f64),
+            (Some(width), None) => (width, aspect_ratio),
+            (None, Some(height)) => (((height as f64 * aspect_ratio).round() as usize).max(1), aspect_ratio),
+            (None, None) => (defaults.image_width, aspect_ratio),
+        };
+
+        scene.set_resolution(width, aspect_ratio);
+    }
+}
+
+// Options of the interactive viewer.
+#[derive(Debug, Clone)]
+pub struct ViewOptions {
+    pub scene: SceneSource,
+    pub resolution: Resolution,
+    pub denoise: bool,
+    pub seed: u64,
+    pub sampler: Sampler,
+    pub filter: Filter,
+    pub display: DisplayTransform,
+}
+
+// Options of the headless renderer, which writes the result to disk.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub scene: SceneSource,
+    pub resolution: Resolution,
+    pub frames: usize,
+    pub output: String,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    pub noise_threshold: Option<f64>,
+    pub seed: u64,
+    pub sampler: Sampler,
+    pub filter: Filter,
+    pub display: DisplayTransform,
+}
+
+// Loads the scene and applies the resolution from the command line.
+pub fn load_scene(source: &SceneSource, resolution: Resolution) -> Result<Scene, String> {
+    let mut scene = source.load()?;
+    resolution.apply(&mut scene);
+    Ok(scene)
+}
+
+// An empty error means help was requested.
+pub fn parse_view_args<I: IntoIterator<Item = String>>(args: I) -> Result<ViewOptions, String> {
+    let options = parse_args(args, false)?;
+    Ok(ViewOptions { scene: options.scene, resolution: options.resolution, denoise: options.denoise, seed: options.seed, sampler: options.sampler, filter: options.filter, display: options.display })
+}
+
+// An empty error means help was requested.
+pub fn parse_render_args<I: IntoIterator<Item = String>>(args: I) -> Result<RenderOptions, String> {
+    parse_args(args, true)
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I, is_render: bool) -> Result<RenderOptions, String> {
+    let mut args = args.into_iter();
+
+    let mut scene = SceneSource::Builtin(DEFAULT_SCENE);
+    let mut resolution = Resolution::default();
+    let mut frames = 100;
+    let mut output = String::from("render.png");
+    let mut aovs = Vec::new();
+    let mut denoise = false;
+    let mut noise_threshold = None;
+    let mut seed = random::DEFAULT_SEED;
+    let mut sampler = Sampler::default();
+    let mut filter_kind = FilterKind::default();
+    let mut filter_radius = None;
+    let mut display = DisplayTransform::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => scene = SceneSource::Builtin(parse_number(&arg, args.next())?),
+            "--scene-file" => scene = SceneSource::File(args.next().ok_or(format!("missing value for {}", arg))?),
+            "--width" => resolution.width = Some(parse_number(&arg, args.next())?),
+            "--height" => resolution.height = Some(parse_number(&arg, args.next())?),
+            "--aspect-ratio" => resolution.aspect_ratio = Some(parse_aspect_ratio(&arg, args.next())?),
+            "--frames" if is_render => frames = parse_number(&arg, args.next())?,
+            "--output" if is_render => output = args.next().ok_or(format!("missing value for {}", arg))?,
+            "--aov" if is_render => aovs = parse_aovs(&arg, args.next())?,
+            "--denoise" => denoise = true,
+            "--seed" => seed = parse_number(&arg, args.next())?,
+            "--sampler" => {
+                let name = args.next().ok_or(format!("missing value for {}", arg))?;
+                sampler = Sampler::from_name(&name).ok_or(format!("unknown sampler '{}' for {}", name, arg))?;
+            },
+            "--filter" => {
+                let name = args.next().ok_or(format!("missing value for {}", arg))?;
+                filter_kind = FilterKind::from_name(&name).ok_or(format!("unknown filter '{}' for {}", name, arg))?;
+            },
+            "--filter-radius" => filter_radius = Some(parse_positive_float(&arg, args.next())?),
+            "--exposure" => display.exposure = parse_finite_float(&arg, args.next())?,
+            "--tonemap" => {
+                let name = args.next().ok_or(format!("missing value for {}", arg))?;
+                display.tone_mapping = ToneMapping::from_name(&name).ok_or(format!("unknown tone mapping '{}' for {}", name, arg))?;
+            },
+            "--noise-threshold" if is_render => noise_threshold = Some(parse_positive_float(&arg, args.next())?),
+            "-h" | "--help" => return Err(String::new()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    if frames == 0 {
+        return Err(String::from("--frames must be at least 1"));
+    }
+
+    if resolution.width == Some(0) || resolution.height == Some(0) {
+        return Err(String::from("--width and --height must be at least 1"));
+    }
+
+    if resolution.width.is_some() && resolution.height.is_some() && resolution.aspect_ratio.is_some() {
+        return Err(String::from("--aspect-ratio cannot be combined with both --width and --height"));
+    }
+
+    let filter = Filter { kind: filter_kind, radius: filter_radius.unwrap_or(filter_kind.default_radius()) };
+
+    Ok(RenderOptions { scene, resolution, frames, output, aovs, denoise, noise_threshold, seed, sampler, filter, display })
+}
+
+// Accepts either a plain ratio such as `1.5` or a `width:height` pair such as `16:9`.
+fn parse_aspect_ratio(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    let invalid = || format!("invalid value '{}' for {}", value, flag);
+
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => {
+            let width: f64 = width.parse().map_err(|_| invalid())?;
+            let height: f64 = height.parse().map_err(|_| invalid())?;
+            width / height
+        },
+        None => value.parse().map_err(|_| invalid())?,
+    };
+
+    if !(ratio > 0.0 && ratio.is_finite()) {
+        return Err(invalid());
+    }
+
+    Ok(ratio)
+}
+
+fn parse_positive_float(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        _ => Err(format!("invalid value '{}' for {}", value, flag)),
+    }
+}
+
+fn parse_finite_float(flag: &str, value: Option<String>) -> Result<f64, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("invalid value '{}' for {}", value, flag)),
+    }
+}
+
+// A comma separated list of output variable names, e.g. `albedo,normal,depth`.
+fn parse_aovs(flag: &str, value: Option<String>) -> Result<Vec<Aov>, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    let mut aovs = Vec::new();
+
+    for name in value.split(',').map(str::trim) {
+        let aov = Aov::from_name(name).ok_or(format!("unknown output variable '{}' for {}", name, flag))?;
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
+        }
+    }
+
+    Ok(aovs)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for {}", flag))?;
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn render_args_default_to_the_default_scene() {
+        let options = parse_render_args(args("")).unwrap();
+
+        assert!(matches!(options.scene, SceneSource::Builtin(DEFAULT_SCENE)));
+        assert_eq!(options.frames, 100);
+        assert_eq!(options.output, "render.png");
+        assert!(options.aovs.is_empty());
+        assert!(!options.denoise);
+        assert_eq!(options.noise_threshold, None);
+        assert_eq!(options.seed, random::DEFAULT_SEED);
+        assert_eq!(options.sampler, Sampler::default());
+        assert_eq!(options.filter, Filter::default());
+        assert_eq!(options.display, DisplayTransform::default());
+    }
+
+    #[test]
+    fn render_args_are_parsed() {
+        let options = parse_render_args(args("--scene-file a.toml --width 320 --aspect-ratio 4:3 --frames 8 --output out.exr \
+            --aov depth,albedo,depth --denoise --noise-threshold 0.05 --seed 5 --sampler halton --filter gaussian --filter-radius 2 \
+            --exposure -1.5 --tonemap aces")).unwrap();
+
+        assert!(matches!(options.scene, SceneSource::File(ref path) if path == "a.toml"));
+        assert_eq!(options.resolution.width, Some(320));
+        assert_eq!(options.resolution.aspect_ratio, Some(4.0 / 3.0));
+        assert_eq!(options.frames, 8);
+        assert_eq!(options.output, "out.exr");
+        assert_eq!(options.aovs, vec![Aov::Depth, Aov::Albedo]);
+        assert!(options.denoise);
+        assert_eq!(options.noise_threshold, Some(0.05));
+        assert_eq!(options.seed, 5);
+        assert_eq!(options.sampler, Sampler::Halton);
+        assert_eq!(options.filter.kind, FilterKind::Gaussian);
+        assert_eq!(options.filter.radius, 2.0);
+        assert_eq!(options.display.exposure, -1.5);
+        assert_eq!(options.display.tone_mapping, ToneMapping::Aces);
+    }
+
+    #[test]
+    fn invalid_args_are_rejected() {
+        let lines = [
+            "--frames 0", "--width 0", "--width 4 --height 3 --aspect-ratio 2", "--scene", "--scene x", "--scene-file", "--bogus",
+            "--aov fog", "--noise-threshold 0", "--noise-threshold inf", "--seed -1", "--sampler sobel", "--filter sinc", "--filter-radius 0", "--exposure NaN", "--exposure inf", "--tonemap filmic",
+        ];
+        for line in lines {
+            assert!(parse_render_args(args(line)).is_err_and(|message| !message.is_empty()), "{}", line);
+        }
+
+        // Only the headless renderer writes files.
+        assert!(parse_view_args(args("--output out.png")).is_err());
+        assert_eq!(parse_render_args(args("--help")).unwrap_err(), "");
+    }
+
+    #[test]
+    fn aspect_ratios_are_numbers_or_pairs() {
+        let parse = |value: &str| parse_aspect_ratio("--aspect-ratio", Some(value.to_string()));
+
+        assert_eq!(parse("16:9"), Ok(16.0 / 9.0));
+        assert_eq!(parse("1.5"), Ok(1.5));
+        for value in ["0", "-1", "1:0", "0:1", "a:b", "16:", "inf", "NaN"] {
+            assert!(parse(value).is_err(), "{}", value);
+        }
+        assert!(parse_aspect_ratio("--aspect-ratio", None).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use rand::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+use crate::library::random;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -12,7 +14,7 @@ impl Color {
         Color { r, g, b }
     }
 
-    pub fn to_u32(&self) -> u32 {
+    pub fn to_u32(self) -> u32 {
         let r = (256.0 * self.r) as u32;
         let g = (256.0 * self.g) as u32;
         let b = (256.0 * self.b) as u32;
@@ -20,20 +22,17 @@ impl Color {
         (r << 16) | (g << 8 ) | b
     }
 
-    pub fn from_u8(r: u8, g: u8, b: u8) -> Color {
-        let r = r as f64 / 255.0;
-        let g = g as f64 / 255.0;
-        let b = b as f64 / 255.0;
-
-        Color::new(r, g, b)
+    pub fn to_rgb8(self) -> [u8; 3] {
+        [
+            (256.0 * self.r) as u8,
+            (256.0 * self.g) as u8,
+            (256.0 * self.b) as u8,
+        ]
     }
 
-    pub fn linear_to_gamma(&self) -> Color {
-        Color::new(
-            self.r.sqrt(),
-            self.g.sqrt(),
-            self.b.sqrt(),
-        )
+    // Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
     }
 
     pub fn clamp(&self) -> Self {
@@ -45,7 +44,7 @@ impl Color {
     }
 
     pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         
         let r = rng.gen::<f64>();
         let g = rng.gen::<f64>();
@@ -55,7 +54,7 @@ impl Color {
     }
 
     pub fn random_range(range: std::ops::Range<f64>) -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         
         let r = rng.gen_range(range.clone());
         let g = rng.gen_range(range.clone());
@@ -85,6 +84,14 @@ impl std::ops::AddAssign for Color {
     }
 }
 
+impl std::ops::Sub<Color> for Color {
+    type Output = Color;
+
+    fn sub(self, other: Color) -> Color {
+        Color::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
 impl std::ops::Mul<f64> for Color {
     type Output = Color;
 
@@ -123,4 +130,46 @@ impl std::ops::Mul<Color> for Color {
             b: self.b * color.b,
         }
     }
-}
\ No newline at end of file
+}
+
+// The sRGB transfer function: linear below a small threshold, a 1/2.4 power above.
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of `linear_to_srgb`, turns an sRGB encoded value in [0, 1] back into linear light.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_function_round_trips() {
+        for step in 0..=1000 {
+            let value = step as f64 / 1000.0;
+
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-12, "{}", value);
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-12, "{}", value);
+        }
+    }
+
+    #[test]
+    fn srgb_transfer_function_matches_known_values() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_to_linear(0.5) - 0.214041).abs() < 1e-6);
+        // Both pieces of the curve meet at the threshold.
+        assert!((linear_to_srgb(0.0031308) - 0.04045).abs() < 1e-5);
+    }
+}
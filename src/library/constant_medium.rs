@@ -1,13 +1,16 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 
 use crate::library::basic_lib::*;
-use crate::library::{hittable::{Hittable, HittableTrait}, material::Material, texture::Texture, color::Color};
+use crate::library::{hittable::{Hittable, HittableTrait}, material::Material, random, texture::Texture, color::Color};
 
 #[derive(Debug, Clone)]
 pub struct ConstantMedium {
     boundary: Box<Hittable>,
     neg_inv_density: f64,
-    phase_function: Material,
+    phase_function: Arc<Material>,
+    material_id: usize,
 }
 
 impl ConstantMedium {
@@ -15,17 +18,27 @@ impl ConstantMedium {
         ConstantMedium {
             boundary: Box::new(boundary),
             neg_inv_density: -1.0 / density,
-            phase_function: Material::IsoTropic(phase_function),
+            phase_function: Arc::new(Material::IsoTropic(phase_function)),
+            material_id: 0,
         }
     }
 
     pub fn new_from_color(boundary: Hittable, density: f64, color: Color) -> Self {
         Self::new(boundary, density, Texture::SolidColor(color))
     }
+
+    pub fn material(&self) -> &Material {
+        &self.phase_function
+    }
+
+    // Index of the material in the scene's material table, handed to every hit.
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
 }
 
 impl HittableTrait for ConstantMedium {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         if let Some(mut hit1) = self.boundary.hit(ray, Interval::universe()) {
             if let Some(mut hit2) = self.boundary.hit(ray, Interval::new(hit1.t + 0.0001, f64::INFINITY)) {
                 if hit1.t < ray_t.min { hit1.t = ray_t.min; }
@@ -41,7 +54,8 @@ impl HittableTrait for ConstantMedium {
 
                 let ray_length = ray.direction.magnitude();
                 let distance_inside_boundary = (hit2.t - hit1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * Material::random_float().ln();
+                // Kept away from zero, whose logarithm would put the scattering point at infinity.
+                let hit_distance = self.neg_inv_density * random::sample_1d().max(f64::MIN_POSITIVE).ln();
 
                 if hit_distance > distance_inside_boundary {
                     return None;
@@ -53,7 +67,7 @@ impl HittableTrait for ConstantMedium {
                 let outward_normal = Vector3::new(1.0, 0.0, 0.0);  // arbitrary
                 let material = &self.phase_function;
 
-                return Some(HitObject::new(point, ray, outward_normal, material.clone(), t, 0.0, 0.0))
+                return Some(HitObject::new(point, ray, outward_normal, material, t, 0.0, 0.0).with_material_id(self.material_id))
             }
         }
 
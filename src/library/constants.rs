@@ -1,3 +1,3 @@
-const ASPECT_RATIO: f64 = 1.0;
-pub const WIDTH: usize = 800;
-pub const HEIGHT: usize = (WIDTH as f64 / ASPECT_RATIO) as usize;
+// Resolution used when neither the scene nor the command line sets one.
+pub const DEFAULT_IMAGE_WIDTH: usize = 800;
+pub const DEFAULT_ASPECT_RATIO: f64 = 1.0;
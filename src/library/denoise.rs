@@ -0,0 +1,162 @@
+use rayon::prelude::*;
+
+use crate::library::{aov::Aov, color::Color, renderer::Framebuffer};
+
+// B3 spline taps of the à-trous kernel, applied separately along x and y.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Below this an albedo channel is treated as one, so black surfaces keep their color instead of
+// being divided by zero.
+const MIN_ALBEDO: f64 = 1e-3;
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al., 2010). The image is blurred with a 5x5
+// kernel whose taps spread further apart every pass, and each tap is weighted down where the
+// normal, depth or brightness differ from the center pixel, so edges and shadow boundaries stay sharp.
+//
+// Emission and surface color are taken out before filtering and put back afterwards, so only the
+// noisy incoming light is blurred while textures stay crisp.
+#[derive(Debug, Clone, Copy)]
+pub struct Denoiser {
+    // Number of filter passes, every pass doubles the distance between the taps.
+    pub iterations: usize,
+    // How many standard deviations of the estimated noise a brightness difference may span before it is
+    // treated as an edge. Higher values blur more.
+    pub color_sigma: f64,
+    // Exponent of the cosine between normals, higher values keep more geometric detail.
+    pub normal_power: f64,
+    // Relative depth difference per pixel of distance that is still treated as the same surface.
+    pub depth_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            color_sigma: 4.0,
+            normal_power: 64.0,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+impl Denoiser {
+    // Output variables the renderer has to accumulate for `denoise`.
+    pub const GUIDES: [Aov; 4] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Emission];
+
+    // Returns a copy of the framebuffer with its colors denoised, None if it lacks one of the `GUIDES`.
+    pub fn denoise(&self, framebuffer: &Framebuffer) -> Option<Framebuffer> {
+        let albedo = framebuffer.aov(Aov::Albedo)?;
+        let normal = framebuffer.aov(Aov::Normal)?;
+        let depth = framebuffer.aov(Aov::Depth)?;
+        let emission = framebuffer.aov(Aov::Emission)?;
+
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        let modulation: Vec<Color> = albedo.iter().map(|albedo| Self::modulation(*albedo)).collect();
+
+        let mut irradiance: Vec<Color> = framebuffer.pixels.iter()
+            .zip(emission.iter().zip(modulation.iter()))
+            .map(|(color, (emission, modulation))| {
+                let light = *color - *emission;
+                Color::new(light.r / modulation.r, light.g / modulation.g, light.b / modulation.b)
+            })
+            .collect();
+        let mut variance = Self::spatial_variance(&irradiance, width, height);
+
+        for pass in 0..self.iterations {
+            let guides = Guides { normal, depth, width, height };
+            (irradiance, variance) = self.filter_pass(&irradiance, &variance, &guides, 1 << pass);
+        }
+
+        let mut denoised = framebuffer.clone();
+        denoised.pixels = irradiance.iter()
+            .zip(emission.iter().zip(modulation.iter()))
+            .map(|(irradiance, (emission, modulation))| *irradiance * *modulation + *emission)
+            .collect();
+
+        Some(denoised)
+    }
+
+    fn modulation(albedo: Color) -> Color {
+        let channel = |value: f64| if value > MIN_ALBEDO { value } else { 1.0 };
+        Color::new(channel(albedo.r), channel(albedo.g), channel(albedo.b))
+    }
+
+    // Noise estimate of every pixel: the luminance variance of its 3x3 neighbourhood.
+    fn spatial_variance(pixels: &[Color], width: usize, height: usize) -> Vec<f64> {
+        (0..pixels.len()).into_par_iter().map(|index| {
+            let (x, y) = ((index % width) as isize, (index / width) as isize);
+            let (mut sum, mut sum_squared, mut count) = (0.0, 0.0, 0.0);
+
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (qx, qy) = (x + dx, y + dy);
+                    if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                        continue;
+                    }
+
+                    let luminance = pixels[qy as usize * width + qx as usize].luminance();
+                    sum += luminance;
+                    sum_squared += luminance * luminance;
+                    count += 1.0;
+                }
+            }
+
+            let mean = sum / count;
+            (sum_squared / count - mean * mean).max(0.0)
+        }).collect()
+    }
+
+    // One à-trous pass with taps `step` pixels apart. The variance is filtered along with the colors,
+    // so the brightness test relaxes as the noise goes down.
+    fn filter_pass(&self, pixels: &[Color], variance: &[f64], guides: &Guides, step: usize) -> (Vec<Color>, Vec<f64>) {
+        let (width, height) = (guides.width, guides.height);
+
+        (0..pixels.len()).into_par_iter().map(|index| {
+            let (x, y) = ((index % width) as isize, (index / width) as isize);
+            let (color, normal, depth) = (pixels[index], guides.normal[index], guides.depth[index].r);
+            let luminance_sigma = self.color_sigma * variance[index].sqrt() + 1e-4;
+
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            let mut variance_sum = 0.0;
+            let mut weight_sum = 0.0;
+
+            for (ky, kernel_y) in KERNEL.iter().enumerate() {
+                for (kx, kernel_x) in KERNEL.iter().enumerate() {
+                    let (dx, dy) = ((kx as isize - 2) * step as isize, (ky as isize - 2) * step as isize);
+                    let (qx, qy) = (x + dx, y + dy);
+                    if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                        continue;
+                    }
+
+                    let q = qy as usize * width + qx as usize;
+                    let distance = ((dx * dx + dy * dy) as f64).sqrt();
+
+                    let normal_q = guides.normal[q];
+                    let cosine = normal.r * normal_q.r + normal.g * normal_q.g + normal.b * normal_q.b;
+                    let normal_weight = if q == index { 1.0 } else { cosine.max(0.0).powf(self.normal_power) };
+
+                    let depth_q = guides.depth[q].r;
+                    let depth_scale = self.depth_sigma * depth.max(depth_q) * distance + 1e-6;
+                    let depth_weight = (-(depth - depth_q).abs() / depth_scale).exp();
+
+                    let luminance_weight = (-(color.luminance() - pixels[q].luminance()).abs() / luminance_sigma).exp();
+
+                    let weight = kernel_x * kernel_y * normal_weight * depth_weight * luminance_weight;
+                    sum += pixels[q] * weight;
+                    variance_sum += weight * weight * variance[q];
+                    weight_sum += weight;
+                }
+            }
+
+            (sum * (1.0 / weight_sum), variance_sum / (weight_sum * weight_sum))
+        }).unzip()
+    }
+}
+
+// Per-pixel guide images shared by all passes.
+struct Guides<'a> {
+    normal: &'a [Color],
+    depth: &'a [Color],
+    width: usize,
+    height: usize,
+}
@@ -1,4 +1,4 @@
-pub fn draw_text(buffer: &mut Vec<u32>, text: &str, x: usize, y: usize, color: u32, width: usize) {
+pub fn draw_text(buffer: &mut [u32], text: &str, x: usize, y: usize, color: u32, width: usize) {
     let font = [
         [ 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00 ],   // U+0000 (nul)
         [ 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00 ],   // U+0001
@@ -135,13 +135,15 @@ pub fn draw_text(buffer: &mut Vec<u32>, text: &str, x: usize, y: usize, color: u
 
     for ch in text.chars() {
         let index = ch as usize;
-        if index >= 32 && index < 128 {
+        if (32..128).contains(&index) {
             let mut mask = 0x80;
-            for row in 0..8 {
+            for glyph_row in font[index] {
                 for col in (0..8).rev() {
-                    if font[index][row] & mask != 0 {
-                        // Set pixel at (offset_x + col, offset_y + row) with the specified color
-                        buffer[offset_y * width + offset_x + col] = color;
+                    // Set pixel at (offset_x + col, offset_y + row) with the specified color,
+                    // text running past the edge of small images is clipped.
+                    let index = offset_y * width + offset_x + col;
+                    if glyph_row & mask != 0 && offset_x + col < width && index < buffer.len() {
+                        buffer[index] = color;
                     }
                     mask >>= 1;
                 }
@@ -0,0 +1,207 @@
+use crate::library::{color::Color, filter::Filter};
+
+// Running statistics of the samples taken inside a pixel so far, together with the filtered
+// contributions of all samples around it.
+#[derive(Debug, Clone, Copy)]
+pub struct FilmPixel {
+    sum: Color,
+    luminance_squared_sum: f64,
+    samples: usize,
+    filtered_sum: Color,
+    filter_weight: f64,
+}
+
+impl Default for FilmPixel {
+    fn default() -> Self {
+        FilmPixel {
+            sum: Color::new(0.0, 0.0, 0.0),
+            luminance_squared_sum: 0.0,
+            samples: 0,
+            filtered_sum: Color::new(0.0, 0.0, 0.0),
+            filter_weight: 0.0,
+        }
+    }
+}
+
+impl FilmPixel {
+    pub fn add_sample(&mut self, color: Color) {
+        let luminance = color.luminance();
+
+        self.sum += color;
+        self.luminance_squared_sum += luminance * luminance;
+        self.samples += 1;
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn mean(&self) -> Color {
+        self.sum * (1.0 / self.samples.max(1) as f64)
+    }
+
+    // The reconstructed color: the samples around the pixel weighted by the filter. Falls back to the
+    // mean while no sample has weight here.
+    pub fn color(&self) -> Color {
+        if self.filter_weight.abs() < 1e-12 {
+            return self.mean();
+        }
+
+        self.filtered_sum * (1.0 / self.filter_weight)
+    }
+
+    // Standard error of the mean luminance relative to the mean itself, infinite until there are
+    // enough samples to estimate it.
+    pub fn relative_error(&self) -> f64 {
+        if self.samples < 2 {
+            return f64::INFINITY;
+        }
+
+        let n = self.samples as f64;
+        let mean = self.sum.luminance() / n;
+        let variance = ((self.luminance_squared_sum - n * mean * mean) / (n - 1.0)).max(0.0);
+
+        (variance / n).sqrt() / mean.max(MIN_LUMINANCE)
+    }
+}
+
+// Filtered contributions of the samples taken in a band of rows. Filters wider than half a pixel
+// spill over into the rows around the band, so bands are rendered in parallel into their own tiles,
+// which are added to the film one after the other.
+pub struct SplatTile {
+    first_row: usize,
+    width: usize,
+    sums: Vec<(Color, f64)>,
+}
+
+impl SplatTile {
+    // Tile for the rows `rows` of an image, extended by as many rows as the filter reaches.
+    pub fn new(rows: std::ops::Range<usize>, width: usize, height: usize, filter: &Filter) -> Self {
+        let margin = filter.radius.ceil() as usize;
+        let first_row = rows.start.saturating_sub(margin);
+        let last_row = (rows.end + margin).min(height);
+
+        SplatTile { first_row, width, sums: vec![(Color::new(0.0, 0.0, 0.0), 0.0); (last_row - first_row) * width] }
+    }
+
+    // Adds a sample at the continuous image position `(x, y)`, measured in pixels from the center of the
+    // top left pixel, to every pixel the filter reaches.
+    pub fn splat(&mut self, x: f64, y: f64, color: Color, filter: &Filter) {
+        // Samples never lie more than half a pixel outside the image, so the upper bounds are not negative.
+        let last_row = self.first_row + self.sums.len() / self.width - 1;
+        let columns = (x - filter.radius).ceil().max(0.0) as usize..=((x + filter.radius).floor() as usize).min(self.width - 1);
+        let rows = (y - filter.radius).ceil().max(self.first_row as f64) as usize..=((y + filter.radius).floor() as usize).min(last_row);
+
+        for row in rows {
+            for column in columns.clone() {
+                let weight = filter.evaluate(column as f64 - x, row as f64 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+
+                let (sum, weight_sum) = &mut self.sums[(row - self.first_row) * self.width + column];
+                *sum += color * weight;
+                *weight_sum += weight;
+            }
+        }
+    }
+
+    pub fn add_to(&self, film: &mut [FilmPixel]) {
+        let offset = self.first_row * self.width;
+
+        for (pixel, (sum, weight)) in film[offset..offset + self.sums.len()].iter_mut().zip(self.sums.iter()) {
+            pixel.filtered_sum += *sum;
+            pixel.filter_weight += weight;
+        }
+    }
+}
+
+// Keeps nearly black pixels from needing an unreachable number of samples.
+const MIN_LUMINANCE: f64 = 1e-3;
+
+// Spends samples where the image is still noisy: pixels whose relative error is below the threshold
+// stop receiving samples, and noisier pixels get more of them every frame.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    // Relative standard error of a pixel's luminance at which it counts as converged.
+    pub noise_threshold: f64,
+    // Samples every pixel receives before its error estimate is trusted.
+    pub min_samples: usize,
+    // Upper limit on the multiple of the scene's samples per pixel a noisy pixel gets in one frame.
+    pub max_boost: f64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(noise_threshold: f64) -> Self {
+        AdaptiveSampling { noise_threshold, min_samples: 16, max_boost: 4.0 }
+    }
+
+    pub fn is_converged(&self, pixel: &FilmPixel) -> bool {
+        pixel.samples() >= self.min_samples && pixel.relative_error() <= self.noise_threshold
+    }
+
+    // Number of samples the pixel receives in the next frame.
+    pub fn samples_for(&self, pixel: &FilmPixel, samples_per_pixel: usize) -> usize {
+        if pixel.samples() < self.min_samples {
+            return samples_per_pixel;
+        }
+
+        if self.is_converged(pixel) {
+            return 0;
+        }
+
+        let boost = (pixel.relative_error() / self.noise_threshold).min(self.max_boost);
+        (samples_per_pixel as f64 * boost).ceil() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::filter::FilterKind;
+
+    fn pixel_with(values: &[f64]) -> FilmPixel {
+        let mut pixel = FilmPixel::default();
+        for value in values {
+            // Gray colors have their value as luminance.
+            pixel.add_sample(Color::new(*value, *value, *value));
+        }
+        pixel
+    }
+
+    #[test]
+    fn relative_error_needs_two_samples() {
+        assert_eq!(pixel_with(&[]).relative_error(), f64::INFINITY);
+        assert_eq!(pixel_with(&[0.5]).relative_error(), f64::INFINITY);
+    }
+
+    #[test]
+    fn relative_error_is_the_standard_error_over_the_mean() {
+        assert_eq!(pixel_with(&[0.5, 0.5, 0.5]).relative_error(), 0.0);
+
+        // Mean 2, sample variance 2, standard error sqrt(2 / 2) = 1.
+        assert!((pixel_with(&[1.0, 3.0]).relative_error() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn relative_error_of_black_pixels_stays_finite() {
+        let error = pixel_with(&[0.0, 0.0, 1e-6]).relative_error();
+        assert!(error.is_finite() && error < 1.0);
+    }
+
+    #[test]
+    fn splats_are_weighted_by_the_filter() {
+        let filter = Filter::new(FilterKind::Tent);
+        let mut film = vec![FilmPixel::default(); 3];
+        let mut tile = SplatTile::new(0..1, 3, 1, &filter);
+
+        // Halfway between the first two pixels, then on the second one, out of reach of the third.
+        tile.splat(0.5, 0.0, Color::new(1.0, 1.0, 1.0), &filter);
+        tile.splat(1.0, 0.0, Color::new(4.0, 4.0, 4.0), &filter);
+        tile.add_to(&mut film);
+
+        assert!((film[0].color().r - 1.0).abs() < 1e-12);
+        assert!((film[1].color().r - (0.5 * 1.0 + 4.0) / 1.5).abs() < 1e-12);
+        assert_eq!(film[2].color().r, 0.0);
+    }
+}
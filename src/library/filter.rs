@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+
+// Shape of a pixel reconstruction filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterKind {
+    // Averages the samples inside each pixel.
+    #[default]
+    Box,
+    // Linear falloff from the pixel center.
+    Tent,
+    // Gaussian with a standard deviation of a third of the radius, shifted down to reach zero at the radius.
+    Gaussian,
+    // Mitchell-Netravali cubic with B = C = 1/3, slightly sharpening.
+    Mitchell,
+    // Windowed sinc with as many lobes as the radius, the sharpest of them but prone to ringing.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    // Radius in pixels the filter is usually used with.
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+// Weights the samples around a pixel by their distance to its center. Every sample adds its color
+// to all pixels within the radius, so filters wider than half a pixel blend neighbouring pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // Distance from the pixel center, in pixels, beyond which samples have no weight.
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(FilterKind::default())
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Filter { kind, radius: kind.default_radius() }
+    }
+
+    // Weight of a sample at the given offset from a pixel center.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(self.radius)
+            },
+            FilterKind::Mitchell => Self::mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => Self::sinc(x) * Self::sinc(x / self.radius),
+        }
+    }
+
+    // Mitchell-Netravali cubic on [0, 2] with B = C = 1/3.
+    fn mitchell(x: f64) -> f64 {
+        let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+
+        let value = if x < 1.0 {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+        } else {
+            (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+        };
+
+        value / 6.0
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if x < 1e-5 {
+            return 1.0;
+        }
+
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_vanish_at_the_radius() {
+        for kind in FilterKind::ALL {
+            let filter = Filter::new(kind);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", kind.name());
+
+            for offset in [filter.radius, -filter.radius, filter.radius + 0.25] {
+                assert_eq!(filter.evaluate(offset, 0.0), 0.0, "{} at {}", kind.name(), offset);
+                assert_eq!(filter.evaluate(0.0, offset), 0.0, "{} at {}", kind.name(), offset);
+            }
+        }
+    }
+
+    #[test]
+    fn filters_are_symmetric_and_separable() {
+        for kind in FilterKind::ALL {
+            let filter = Filter::new(kind);
+            let center = filter.evaluate(0.0, 0.0);
+
+            for (dx, dy) in [(0.3, 0.7), (1.1, -0.4), (-0.2, 1.6)] {
+                let weight = filter.evaluate(dx, dy);
+                assert!((weight - filter.evaluate(-dx, dy)).abs() < 1e-12, "{}", kind.name());
+                assert!((weight - filter.evaluate(dy, dx)).abs() < 1e-12, "{}", kind.name());
+                assert!((weight * center - filter.evaluate(dx, 0.0) * filter.evaluate(0.0, dy)).abs() < 1e-12, "{}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn box_is_flat_and_tent_is_linear() {
+        assert_eq!(Filter::new(FilterKind::Box).evaluate(0.49, -0.2), 1.0);
+
+        let tent = Filter::new(FilterKind::Tent);
+        assert!((tent.evaluate(0.5, 0.0) - 0.5).abs() < 1e-12);
+        assert!((tent.evaluate(0.25, 0.5) - 0.375).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gaussian_falls_off_continuously_to_zero() {
+        let filter = Filter::new(FilterKind::Gaussian);
+        let weights: Vec<f64> = (0..15).map(|step| filter.evaluate(step as f64 * 0.1, 0.0)).collect();
+
+        assert!(weights.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(filter.evaluate(filter.radius - 1e-6, 0.0) < 1e-6);
+    }
+
+    #[test]
+    fn mitchell_integrates_to_one_with_a_negative_lobe() {
+        let filter = Filter::new(FilterKind::Mitchell);
+        // The peak of the filter along one axis, (6 - 2B) / 6.
+        let peak = 8.0 / 9.0;
+        assert!((filter.evaluate(0.0, 0.0) - peak * peak).abs() < 1e-12);
+
+        // Along one axis, at its default radius of 2 pixels.
+        let step = 1e-3;
+        let integral: f64 = (-2000..2000).map(|i| filter.evaluate((i as f64 + 0.5) * step, 0.0) / peak * step).sum();
+        assert!((integral - 1.0).abs() < 1e-6);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+    }
+
+    #[test]
+    fn lanczos_crosses_zero_at_whole_pixels() {
+        let filter = Filter::new(FilterKind::Lanczos);
+
+        assert_eq!(filter.evaluate(0.0, 0.0), 1.0);
+        assert!(filter.evaluate(1.0, 0.0).abs() < 1e-12);
+        assert!(filter.evaluate(2.0, 0.0).abs() < 1e-12);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+        assert!(filter.evaluate(2.5, 0.0) > 0.0);
+    }
+}
@@ -1,21 +1,23 @@
 use std::time::Instant;
 use crate::library::draw;
-use crate::library::constants::WIDTH;
 
 pub struct FpsCounter {
     last_second: Instant,
     frame: usize,
+    width: usize,
 }
 
 impl FpsCounter {
-    pub fn start() -> Self {
+    // `width` is the width in pixels of the buffers the counter is drawn into.
+    pub fn start(width: usize) -> Self {
         FpsCounter {
             last_second: Instant::now(),
             frame: 1,
+            width,
         }
     }
 
-    pub fn update(&mut self, clamped_buffer: &mut Vec<u32>) {
+    pub fn update(&mut self, clamped_buffer: &mut [u32]) {
         let frame_start = Instant::now();
 
         let frame_duration_ms = frame_start.duration_since(self.last_second).as_millis();
@@ -25,7 +27,7 @@ impl FpsCounter {
         self.last_second = Instant::now();
         self.frame += 1;
 
-        draw::draw_text(clamped_buffer, &fps_text, 5, 5, 0x00FF00, WIDTH);
-        draw::draw_text(clamped_buffer, &frame_text, 5, 18, 0x00FF00, WIDTH);
+        draw::draw_text(clamped_buffer, &fps_text, 5, 5, 0x00FF00, self.width);
+        draw::draw_text(clamped_buffer, &frame_text, 5, 18, 0x00FF00, self.width);
     }
 }
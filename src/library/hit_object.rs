@@ -1,26 +1,121 @@
 use nalgebra::Vector3;
 use crate::library::{material::Material, ray::Ray};
 
+// Borrows the material from the object that was hit, so intersections never copy it.
 #[derive(Debug, Clone)]
-pub struct HitObject {
+pub struct HitObject<'a> {
     pub point: Vector3<f64>,
+    // Shading normal, may differ from the geometric normal when it is interpolated across a surface.
     pub normal: Vector3<f64>,
-    pub material: Material,
+    // Normal of the actual surface, both normals always point to the side the ray came from.
+    pub geometric_normal: Vector3<f64>,
+    pub material: &'a Material,
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // Index of the top-level scene object that was hit, filled in by the bounding volume hierarchy.
+    pub object_id: usize,
+    // Index of the material in the scene's material table, filled in by the primitive that was hit.
+    pub material_id: usize,
+    pub differentials: SurfaceDifferentials,
 }
 
-impl HitObject {
-    pub fn new(point: Vector3<f64>, ray: Ray, outward_normal: Vector3<f64>, material: Material, t: f64, u: f64, v: f64) -> Self {
+// How the hit point and the texture coordinates change from one pixel to the next, used to filter
+// textures over the area a pixel covers. All zero when the ray carries no differentials.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceDifferentials {
+    pub dpdx: Vector3<f64>,
+    pub dpdy: Vector3<f64>,
+    pub du_dx: f64,
+    pub dv_dx: f64,
+    pub du_dy: f64,
+    pub dv_dy: f64,
+}
+
+impl Default for SurfaceDifferentials {
+    fn default() -> Self {
+        SurfaceDifferentials {
+            dpdx: Vector3::new(0.0, 0.0, 0.0),
+            dpdy: Vector3::new(0.0, 0.0, 0.0),
+            du_dx: 0.0,
+            dv_dx: 0.0,
+            du_dy: 0.0,
+            dv_dy: 0.0,
+        }
+    }
+}
+
+impl<'a> HitObject<'a> {
+    pub fn new(point: Vector3<f64>, ray: Ray, outward_normal: Vector3<f64>, material: &'a Material, t: f64, u: f64, v: f64) -> Self {
+        Self::new_with_shading_normal(point, ray, outward_normal, outward_normal, material, t, u, v)
+    }
+
+    // The side of the surface that was hit is decided by the geometric normal alone,
+    // the shading normal is flipped onto that same side.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_shading_normal(point: Vector3<f64>, ray: Ray, outward_normal: Vector3<f64>, shading_normal: Vector3<f64>, material: &'a Material, t: f64, u: f64, v: f64) -> Self {
         let front_face = ray.direction.dot(&outward_normal) < 0.0;
 
-        let mut normal = -outward_normal;
+        let mut geometric_normal = -outward_normal;
         if front_face {
-            normal = outward_normal;
+            geometric_normal = outward_normal;
+        }
+
+        let mut normal = shading_normal;
+        if normal.dot(&geometric_normal) < 0.0 {
+            normal = -normal;
+        }
+
+        HitObject { point, normal, geometric_normal, material, t, u, v, front_face, object_id: 0, material_id: 0, differentials: SurfaceDifferentials::default() }
+    }
+
+    pub fn with_material_id(mut self, material_id: usize) -> Self {
+        self.material_id = material_id;
+        self
+    }
+
+    // Fills in the differentials from the ray's and the surface's partial derivatives `dpdu` and `dpdv`
+    // at the hit point. The neighbouring pixels' rays are intersected with the tangent plane and the
+    // offsets to their hit points are expressed in terms of the derivatives (Igehy's ray differentials).
+    pub fn with_differentials(mut self, ray: &Ray, dpdu: Vector3<f64>, dpdv: Vector3<f64>) -> Self {
+        let Some(differentials) = ray.differentials else {
+            return self;
+        };
+
+        let normal = self.geometric_normal;
+        let plane_offset = |origin: Vector3<f64>, direction: Vector3<f64>| {
+            let denominator = normal.dot(&direction);
+            if denominator.abs() < 1e-12 {
+                return None;
+            }
+
+            let t = normal.dot(&(self.point - origin)) / denominator;
+            Some(origin + t * direction - self.point)
+        };
+
+        let (Some(dpdx), Some(dpdy)) = (
+            plane_offset(differentials.x_origin, differentials.x_direction),
+            plane_offset(differentials.y_origin, differentials.y_direction),
+        ) else {
+            return self;
+        };
+
+        // Least squares solution of dpdx = dpdu * du_dx + dpdv * dv_dx, and the same for y.
+        let (a00, a01, a11) = (dpdu.dot(&dpdu), dpdu.dot(&dpdv), dpdv.dot(&dpdv));
+        let inverse_determinant = 1.0 / (a00 * a11 - a01 * a01);
+        if !inverse_determinant.is_finite() {
+            return self;
         }
 
-        HitObject { point, normal, material, t, u, v, front_face }
+        let solve = |offset: Vector3<f64>| {
+            let (b0, b1) = (dpdu.dot(&offset), dpdv.dot(&offset));
+            ((a11 * b0 - a01 * b1) * inverse_determinant, (a00 * b1 - a01 * b0) * inverse_determinant)
+        };
+        let (du_dx, dv_dx) = solve(dpdx);
+        let (du_dy, dv_dy) = solve(dpdy);
+
+        self.differentials = SurfaceDifferentials { dpdx, dpdy, du_dx, dv_dx, du_dy, dv_dy };
+        self
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,30 @@
+use nalgebra::Vector3;
+
 use crate::library::basic_lib::*;
 use crate::library::rotate_y::RotateY;
 use crate::library::{sphere::Sphere, quad::Quad, translate::Translate, quadbox::Quadbox};
 
 use super::constant_medium::ConstantMedium;
 use super::triangle::Triangle;
+use super::bvh::BvhNode;
+use super::mesh::TriangleMesh;
+use super::material::Material;
 
 pub trait HittableTrait: Sync + Send {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject>;
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>>;
 
     fn get_bounding_box(&self) -> Aabb;
+
+    // Solid angle density of `random` generating the given direction from the origin.
+    // Only objects that can be sampled as lights return a non-zero density.
+    fn pdf_value(&self, _origin: Vector3<f64>, _direction: Vector3<f64>) -> f64 {
+        0.0
+    }
+
+    // Random direction from the origin towards a point on the object.
+    fn random(&self, _origin: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -20,11 +36,12 @@ pub enum Hittable {
     RotateY(Box<RotateY>),
     ConstantMedium(ConstantMedium),
     Triangle(Triangle),
-    // Bvh(Box<BvhNode>)
+    Bvh(Box<BvhNode>),
+    TriangleMesh(TriangleMesh),
 }
 
 impl Hittable {
-    pub fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    pub fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         match self {
             Hittable::Sphere(sphere) => sphere.hit(ray, ray_t),
             Hittable::Quad(quad) => quad.hit(ray, ray_t),
@@ -33,7 +50,8 @@ impl Hittable {
             Hittable::RotateY(rotate_y) => rotate_y.hit(ray, ray_t),
             Hittable::ConstantMedium(medium) => medium.hit(ray, ray_t),
             Hittable::Triangle(triangle) => triangle.hit(ray, ray_t),
-            // Hittable::Bvh(bvh) => bvh.hit(ray, ray_t),
+            Hittable::Bvh(bvh) => bvh.hit(ray, ray_t),
+            Hittable::TriangleMesh(mesh) => mesh.hit(ray, ray_t),
         }
     }
 
@@ -46,7 +64,98 @@ impl Hittable {
             Hittable::RotateY(rotate_y) => rotate_y.get_bounding_box(),
             Hittable::ConstantMedium(medium) => medium.get_bounding_box(),
             Hittable::Triangle(triangle) => triangle.get_bounding_box(),
-            // Hittable::Bvh(bvh) => bvh.get_bounding_box(),
+            Hittable::Bvh(bvh) => bvh.get_bounding_box(),
+            Hittable::TriangleMesh(mesh) => mesh.get_bounding_box(),
+        }
+    }
+    pub fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        match self {
+            Hittable::Sphere(sphere) => sphere.pdf_value(origin, direction),
+            Hittable::Quad(quad) => quad.pdf_value(origin, direction),
+            Hittable::Triangle(triangle) => triangle.pdf_value(origin, direction),
+            Hittable::Translate(translate) => translate.pdf_value(origin, direction),
+            Hittable::RotateY(rotate_y) => rotate_y.pdf_value(origin, direction),
+            Hittable::TriangleMesh(mesh) => mesh.pdf_value(origin, direction),
+            _ => 0.0,
+        }
+    }
+
+    pub fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        match self {
+            Hittable::Sphere(sphere) => sphere.random(origin),
+            Hittable::Quad(quad) => quad.random(origin),
+            Hittable::Triangle(triangle) => triangle.random(origin),
+            Hittable::Translate(translate) => translate.random(origin),
+            Hittable::RotateY(rotate_y) => rotate_y.random(origin),
+            Hittable::TriangleMesh(mesh) => mesh.random(origin),
+            _ => Vector3::new(1.0, 0.0, 0.0),
         }
     }
-}
\ No newline at end of file
+
+    // Appends the emissive spheres, quads, triangles and meshes to the list of lights, descending into
+    // groups and boxes. Lights inside transforms are collected wrapped in the same transform.
+    // Lights inside media are not sampled directly and are only found by scattering.
+    pub fn collect_lights(&self, lights: &mut Vec<Hittable>) {
+        match self {
+            Hittable::Sphere(sphere) if sphere.material().is_emissive() => lights.push(self.clone()),
+            Hittable::Quad(quad) if quad.material().is_emissive() => lights.push(self.clone()),
+            Hittable::Triangle(triangle) if triangle.material().is_emissive() => lights.push(self.clone()),
+            Hittable::TriangleMesh(mesh) if mesh.material().is_emissive() => lights.push(self.clone()),
+            Hittable::Bvh(bvh) => {
+                for child in bvh.children() {
+                    child.collect_lights(lights);
+                }
+            },
+            Hittable::QuadBox(quad_box) => {
+                for side in &quad_box.objects {
+                    side.collect_lights(lights);
+                }
+            },
+            Hittable::Translate(translate) => {
+                let mut inner = Vec::new();
+                translate.object().collect_lights(&mut inner);
+                lights.extend(inner.into_iter().map(|light| Hittable::Translate(Box::new(translate.with_object(light)))));
+            },
+            Hittable::RotateY(rotate_y) => {
+                let mut inner = Vec::new();
+                rotate_y.object().collect_lights(&mut inner);
+                lights.extend(inner.into_iter().map(|light| Hittable::RotateY(Box::new(rotate_y.with_object(light)))));
+            },
+            _ => {},
+        }
+    }
+
+    // Gives every primitive the index of its material in `materials`, descending into groups, boxes,
+    // transforms and media. Materials equal to one already in the table share its index, others are appended.
+    pub fn assign_material_ids(&mut self, materials: &mut Vec<Material>) {
+        match self {
+            Hittable::Sphere(sphere) => sphere.set_material_id(material_index(materials, sphere.material())),
+            Hittable::Quad(quad) => quad.set_material_id(material_index(materials, quad.material())),
+            Hittable::Triangle(triangle) => triangle.set_material_id(material_index(materials, triangle.material())),
+            Hittable::TriangleMesh(mesh) => mesh.set_material_id(material_index(materials, mesh.material())),
+            Hittable::ConstantMedium(medium) => medium.set_material_id(material_index(materials, medium.material())),
+            Hittable::QuadBox(quad_box) => {
+                for side in &mut quad_box.objects {
+                    side.assign_material_ids(materials);
+                }
+            },
+            Hittable::Translate(translate) => translate.object_mut().assign_material_ids(materials),
+            Hittable::RotateY(rotate_y) => rotate_y.object_mut().assign_material_ids(materials),
+            Hittable::Bvh(bvh) => {
+                for child in bvh.children_mut() {
+                    child.assign_material_ids(materials);
+                }
+            },
+        }
+    }
+}
+
+fn material_index(materials: &mut Vec<Material>, material: &Material) -> usize {
+    match materials.iter().position(|known| known == material) {
+        Some(index) => index,
+        None => {
+            materials.push(material.clone());
+            materials.len() - 1
+        },
+    }
+}
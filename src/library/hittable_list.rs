@@ -1,5 +1,5 @@
 use crate::library::basic_lib::*;
-use crate::library::hittable::Hittable;
+use crate::library::{hittable::Hittable, bvh::BvhNode};
 
 #[derive(Debug, Clone)]
 pub struct HittableList {
@@ -7,11 +7,17 @@ pub struct HittableList {
     bounding_box: Aabb,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
             objects: Vec::new(),
-            bounding_box: Aabb::new(Interval::empty(), Interval::empty(), Interval::empty()),
+            bounding_box: Aabb::empty(),
         }
     }
 
@@ -26,11 +32,22 @@ impl HittableList {
         }
     }
 
+    // Replaces the objects with a single bounding volume hierarchy containing all of them.
+    pub fn into_bvh(self) -> Self {
+        if self.objects.len() <= 1 {
+            return self;
+        }
+
+        let mut list = HittableList::new();
+        list.add(Hittable::Bvh(Box::new(BvhNode::new_from_list(self))));
+        list
+    }
+
     fn _get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
 
-    pub fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    pub fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         let mut closest_so_far = ray_t.max;
         let mut temp_hit_object = None;
 
@@ -1,34 +1,182 @@
-use image::{DynamicImage, GenericImageView};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::library::color::Color;
+use image::{DynamicImage, ImageResult};
 
+use crate::library::color::{self, Color};
+
+// How the values stored in an image file relate to linear light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    // Colors painted or photographed for display, stored with the sRGB transfer function.
+    #[default]
+    Srgb,
+    // Data that is used as it is stored, such as roughness, bump or normal maps.
+    Linear,
+}
+
+impl ColorSpace {
+    // Linear value of a stored channel in [0, 1].
+    fn decode(self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb => color::srgb_to_linear(value),
+            ColorSpace::Linear => value,
+        }
+    }
+}
+
+// Image decoded to linear colors once when it is loaded, so texture lookups need no conversion,
+// together with its mipmap for filtering it over larger areas. Clones share the pixels.
 #[derive(Debug, Clone)]
 pub struct Image {
-    pub image: DynamicImage,
+    levels: Arc<[MipLevel]>,
+}
+
+// Images are the same when they share their pixels, as all images loaded from one file through an `ImageCache` do.
+impl PartialEq for Image {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.levels, &other.levels)
+    }
+}
+
+// One resolution of an image, each level halves the width and height of the one before down to a
+// single pixel.
+#[derive(Debug)]
+pub struct MipLevel {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
 }
 
 impl Image {
     pub fn load_image(file_path: &str) -> Self {
-        let image = image::open(file_path).expect("Failed to open image");
-        
-        Image { image }
+        Self::try_load_image(file_path).expect("Failed to open image")
     }
 
+    pub fn try_load_image<P: AsRef<Path>>(file_path: P) -> ImageResult<Self> {
+        Self::try_load_image_with_color_space(file_path, ColorSpace::default())
+    }
+
+    // 8-bit and 16-bit images are decoded with the given color space, floating point images such as
+    // OpenEXR or Radiance HDR files always hold linear values and are used as they are.
+    pub fn try_load_image_with_color_space<P: AsRef<Path>>(file_path: P, color_space: ColorSpace) -> ImageResult<Self> {
+        let image = image::open(file_path)?;
+        let (width, height) = (image.width(), image.height());
+
+        let pixels = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => image.into_rgb32f().pixels()
+                .map(|pixel| Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+                .collect(),
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+                let decode = |value: u16| color_space.decode(value as f64 / u16::MAX as f64);
+                image.into_rgb16().pixels().map(|pixel| Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))).collect()
+            },
+            _ => {
+                // Only 256 values to convert, look them up instead of evaluating the transfer function per pixel.
+                let table: Vec<f64> = (0..=u8::MAX).map(|value| color_space.decode(value as f64 / u8::MAX as f64)).collect();
+                let decode = |value: u8| table[value as usize];
+                image.into_rgb8().pixels().map(|pixel| Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))).collect()
+            },
+        };
+
+        let mut levels = vec![MipLevel { width, height, pixels }];
+        while let Some(level) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(level.downsample());
+        }
+
+        Ok(Image { levels: levels.into() })
+    }
+
+    // Linear color of the pixel, coordinates past the edge are clamped to it.
     pub fn pixel_data(&self, x: u32, y: u32) -> Color {
-        let pixel = self.image.get_pixel(x, y);
+        self.levels[0].pixel(x, y)
+    }
 
-        let r = pixel[0];
-        let g = pixel[1];
-        let b = pixel[2];
+    pub fn width(&self) -> u32 {
+        self.levels[0].width
+    }
 
-        Color::from_u8(r, g, b)
+    pub fn height(&self) -> u32 {
+        self.levels[0].height
     }
 
+    // The mipmap, starting with the full resolution image.
+    pub fn levels(&self) -> &[MipLevel] {
+        &self.levels
+    }
+}
+
+// Images loaded so far by file and color space, so a file used by several textures is read and
+// decoded once and all of them share its pixels.
+#[derive(Debug, Default)]
+pub struct ImageCache {
+    images: HashMap<(PathBuf, ColorSpace), Image>,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(&mut self, file_path: P, color_space: ColorSpace) -> ImageResult<Image> {
+        // Different relative paths to the same file share an entry.
+        let path = file_path.as_ref();
+        let key = (std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()), color_space);
+
+        if let Some(image) = self.images.get(&key) {
+            return Ok(image.clone());
+        }
+
+        let image = Image::try_load_image_with_color_space(path, color_space)?;
+        self.images.insert(key, image.clone());
+        Ok(image)
+    }
+}
+
+impl MipLevel {
     pub fn width(&self) -> u32 {
-        self.image.width()
+        self.width
     }
 
     pub fn height(&self) -> u32 {
-        self.image.height()
+        self.height
+    }
+
+    // Coordinates past the edge are clamped to it.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let x = x.min(self.width.saturating_sub(1));
+        let y = y.min(self.height.saturating_sub(1));
+
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // Averages the pixels each pixel of the next level covers. Odd sizes are rounded down, the
+    // pixels that then straddle two of the new ones count towards both.
+    fn downsample(&self) -> MipLevel {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let covered = |index: u32, size: u32, new_size: u32| {
+            let start = (index as u64 * size as u64 / new_size as u64) as u32;
+            let end = ((index as u64 + 1) * size as u64).div_ceil(new_size as u64) as u32;
+            start..end
+        };
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (rows, columns) = (covered(y, self.height, height), covered(x, self.width, width));
+                let count = (rows.len() * columns.len()) as f64;
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                for row in rows {
+                    for column in columns.clone() {
+                        sum += self.pixel(column, row);
+                    }
+                }
+                pixels.push(sum * (1.0 / count));
+            }
+        }
+
+        MipLevel { width, height, pixels }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,186 @@
+use crate::library::{color::Color, hit_object::SurfaceDifferentials, image::{Image, MipLevel}};
+
+// How texels are combined into the color at a texture coordinate. Textures seen from far away are
+// first looked up in the mipmap level whose texels match the size of a pixel, the linear filters
+// blend the two closest levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextureFilter {
+    // The texel the coordinate falls into, blocky when magnified.
+    Nearest,
+    // Linear blend of the four closest texels.
+    #[default]
+    Bilinear,
+    // Catmull-Rom spline through the sixteen closest texels, sharper than bilinear when magnified.
+    Bicubic,
+}
+
+// What the texture does outside of [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    // Tiles the image.
+    #[default]
+    Repeat,
+    // Stretches the edge texels.
+    Clamp,
+    // Tiles the image, flipping every other copy so neighbouring tiles meet at matching edges.
+    Mirror,
+}
+
+impl WrapMode {
+    // Texel index inside [0, size) for any integer index.
+    fn apply(self, index: i64, size: i64) -> i64 {
+        match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let index = index.rem_euclid(2 * size);
+                if index < size { index } else { 2 * size - 1 - index }
+            },
+        }
+    }
+}
+
+// Placement of the image in texture space: coordinates are scaled, then rotated counterclockwise
+// around the origin and finally offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvTransform {
+    // Number of times the image repeats along u and v.
+    pub scale: (f64, f64),
+    // Rotation in degrees.
+    pub rotation: f64,
+    pub offset: (f64, f64),
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform { scale: (1.0, 1.0), rotation: 0.0, offset: (0.0, 0.0) }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = self.apply_vector(u, v);
+        (u + self.offset.0, v + self.offset.1)
+    }
+
+    // The transform without the offset, for differences of texture coordinates.
+    pub fn apply_vector(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        (cos * u - sin * v, sin * u + cos * v)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    pub image: Image,
+    pub filter: TextureFilter,
+    pub wrap: WrapMode,
+    pub transform: UvTransform,
+    // Multiplies every filtered color, e.g. the emission color of a material whose emission map is this texture.
+    pub tint: Color,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        ImageTexture { image, filter: TextureFilter::default(), wrap: WrapMode::default(), transform: UvTransform::default(), tint: Color::new(1.0, 1.0, 1.0) }
+    }
+
+    pub fn value(&self, u: f64, v: f64, differentials: &SurfaceDifferentials) -> Color {
+        self.tint * self.filtered_value(u, v, differentials)
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, differentials: &SurfaceDifferentials) -> Color {
+        let (u, v) = self.transform.apply(u, v);
+        let (du_dx, dv_dx) = self.transform.apply_vector(differentials.du_dx, differentials.dv_dx);
+        let (du_dy, dv_dy) = self.transform.apply_vector(differentials.du_dy, differentials.dv_dy);
+
+        // Level at which the longer side of the pixel footprint spans one texel.
+        let levels = self.image.levels();
+        let (width, height) = (self.image.width() as f64, self.image.height() as f64);
+        let footprint = (du_dx * width).hypot(dv_dx * height).max((du_dy * width).hypot(dv_dy * height));
+        let level = footprint.max(1e-8).log2().clamp(0.0, (levels.len() - 1) as f64);
+
+        if self.filter == TextureFilter::Nearest {
+            return self.sample(&levels[level.round() as usize], u, v);
+        }
+
+        let lower = level.floor() as usize;
+        let t = level - lower as f64;
+        let color = self.sample(&levels[lower], u, v);
+        if t == 0.0 {
+            return color;
+        }
+
+        color * (1.0 - t) + self.sample(&levels[lower + 1], u, v) * t
+    }
+
+    fn sample(&self, level: &MipLevel, u: f64, v: f64) -> Color {
+        // Continuous texel coordinates with texel centers on whole numbers, V is flipped to image rows.
+        let x = u * level.width() as f64 - 0.5;
+        let y = (1.0 - v) * level.height() as f64 - 0.5;
+
+        match self.filter {
+            TextureFilter::Nearest => self.texel(level, x.round() as i64, y.round() as i64),
+            TextureFilter::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.texel(level, x0, y0) * (1.0 - tx) + self.texel(level, x0 + 1, y0) * tx;
+                let bottom = self.texel(level, x0, y0 + 1) * (1.0 - tx) + self.texel(level, x0 + 1, y0 + 1) * tx;
+                top * (1.0 - ty) + bottom * ty
+            },
+            TextureFilter::Bicubic => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (weights_x, weights_y) = (catmull_rom_weights(x - x0), catmull_rom_weights(y - y0));
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let mut color = Color::new(0.0, 0.0, 0.0);
+                for (row, weight_y) in weights_y.iter().enumerate() {
+                    for (column, weight_x) in weights_x.iter().enumerate() {
+                        color += self.texel(level, x0 + column as i64 - 1, y0 + row as i64 - 1) * (weight_x * weight_y);
+                    }
+                }
+
+                // The spline overshoots next to sharp edges.
+                Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0))
+            },
+        }
+    }
+
+    fn texel(&self, level: &MipLevel, x: i64, y: i64) -> Color {
+        let x = self.wrap.apply(x, level.width() as i64);
+        let y = self.wrap.apply(y, level.height() as i64);
+
+        level.pixel(x as u32, y as u32)
+    }
+}
+
+// Weights of the four texels around a sample that lies `t` past the second of them.
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_modes_map_indices_into_the_image() {
+        let indices = [-5, -4, -1, 0, 1, 3, 4, 7, 8];
+        let wrap = |mode: WrapMode| indices.map(|index| mode.apply(index, 4));
+
+        assert_eq!(wrap(WrapMode::Repeat), [3, 0, 3, 0, 1, 3, 0, 3, 0]);
+        assert_eq!(wrap(WrapMode::Clamp), [0, 0, 0, 0, 1, 3, 3, 3, 3]);
+        assert_eq!(wrap(WrapMode::Mirror), [3, 3, 0, 0, 1, 3, 3, 0, 0]);
+    }
+}
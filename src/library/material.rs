@@ -1,9 +1,11 @@
+use std::f64::consts::PI;
+
 use nalgebra::Vector3;
 use rand::prelude::*;
 
-use crate::library::{color::Color, hit_object::HitObject, ray::Ray, texture::Texture, vector3::Vector3Extensions};
+use crate::library::{color::Color, hit_object::HitObject, onb::Onb, random, ray::Ray, texture::Texture, vector3::Vector3Extensions};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Material {
     Lambertian(Texture),
     Metal(Color, f64),
@@ -12,32 +14,52 @@ pub enum Material {
     IsoTropic(Texture),
 }
 
+// Direction picked by `Material::sample`, together with the BSDF value and density for that direction.
+// Delta lobes scatter into this single direction only: their value is the attenuation and their pdf is one.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialSample {
+    pub ray: Ray,
+    // BSDF times the cosine term, or the phase function for participating media.
+    pub value: Color,
+    // Solid angle density with which the direction was picked.
+    pub pdf: f64,
+    pub is_delta: bool,
+}
+
+impl MaterialSample {
+    // Factor the light arriving along the sampled ray is multiplied by.
+    pub fn attenuation(&self) -> Color {
+        self.value * (1.0 / self.pdf)
+    }
+}
+
 impl Material {
-    pub fn scatter(&self, r_in: Ray, hit_object: &HitObject) -> Option<(Color, Ray)> {
+    // Picks a direction for the light scattered back along `r_in`, None if the ray is absorbed.
+    pub fn sample(&self, r_in: Ray, hit_object: &HitObject) -> Option<MaterialSample> {
         match self {
-            Material::Lambertian(albedo) => {
-                let mut scatter_direction = hit_object.normal + Ray::random_unit_vector();
+            Material::Lambertian(_) => {
+                let mut direction = hit_object.normal + Ray::random_unit_vector();
 
                 // Catch degenerate scatter direction
-                if scatter_direction.near_zero() {
-                    scatter_direction = hit_object.normal;
+                if direction.near_zero() {
+                    direction = hit_object.normal;
                 }
-                
-                let scattered = Ray::new(hit_object.point, scatter_direction, r_in.time);
-                let attenuation = albedo.value(hit_object.u, hit_object.v, hit_object.point);
 
-                Some((attenuation, scattered))
+                self.sample_from_direction(r_in, hit_object, direction)
             },
             Material::Metal(albedo, fuzz) => {
                 let reflected = Ray::reflect(r_in.direction.normalize(), hit_object.normal);
-                let scattered = Ray::new(hit_object.point, reflected + *fuzz * Ray::random_in_unit_sphere(), r_in.time);
-                let attenuation = *albedo;
-                
-                if scattered.direction.dot(&hit_object.normal) > 0.0 {
-                    Some((attenuation, scattered))
-                } else {
-                    None
+                if reflected.dot(&hit_object.geometric_normal) <= 0.0 {
+                    return None;
                 }
+
+                if *fuzz <= 0.0 {
+                    return Some(Self::delta_sample(r_in, hit_object, reflected, *albedo));
+                }
+
+                // Fuzzy reflections are spread over a Phong lobe around the mirror direction.
+                let direction = Onb::new(reflected).transform(Self::random_phong_direction(Self::phong_exponent(*fuzz)));
+                self.sample_from_direction(r_in, hit_object, direction)
             },
             Material::Dielectric(refaction_index) => {
                 let attenuation = Color::new(1.0, 1.0, 1.0);
@@ -51,39 +73,139 @@ impl Material {
 
                 let cos_theta = -unit_direction.dot(&hit_object.normal).min(1.0);
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        
+
                 let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        
+
                 let mut direction = Ray::refract(unit_direction, hit_object.normal, refraction_ratio);
-                if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > Self::random_float() {
+                if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > random::sample_1d() {
                     direction = Ray::reflect(unit_direction, hit_object.normal);
                 }
 
-                let scattered = Ray::new(hit_object.point, direction, r_in.time);
-                
-                Some((attenuation, scattered))
+                Some(Self::delta_sample(r_in, hit_object, direction, attenuation))
             },
             Material::DiffuseLight(_) => {
                 None
             },
+            Material::IsoTropic(_) => {
+                self.sample_from_direction(r_in, hit_object, Ray::random_unit_vector())
+            },
+        }
+    }
+
+    // BSDF times the cosine term for light arriving from `direction` and leaving along `r_in`.
+    // Always zero for delta lobes, which no other direction can hit.
+    pub fn eval(&self, r_in: Ray, hit_object: &HitObject, direction: Vector3<f64>) -> Color {
+        match self {
+            Material::Lambertian(albedo) => {
+                let cosine = hit_object.normal.dot(&direction.normalize()).max(0.0);
+                albedo.value(hit_object.u, hit_object.v, hit_object.point, &hit_object.differentials) * (cosine / PI)
+            },
+            // The lobe is normalized so that it attenuates by the albedo alone.
+            Material::Metal(albedo, fuzz) if *fuzz > 0.0 => {
+                *albedo * self.pdf(r_in, hit_object, direction)
+            },
             Material::IsoTropic(albedo) => {
-                let scattered = Ray::new(hit_object.point, Ray::random_unit_vector(), r_in.time);
-                let attenuation = albedo.value(hit_object.u, hit_object.v, hit_object.point);
-                
-                Some((attenuation, scattered))
+                albedo.value(hit_object.u, hit_object.v, hit_object.point, &hit_object.differentials) * (1.0 / (4.0 * PI))
             },
+            _ => Color::new(0.0, 0.0, 0.0),
         }
     }
 
-    pub fn emitted(&self, u: f64, v: f64, p: Vector3<f64>) -> Color {
+    // Solid angle density with which `sample` picks `direction` for a ray arriving along `r_in`.
+    // Always zero for delta lobes.
+    pub fn pdf(&self, r_in: Ray, hit_object: &HitObject, direction: Vector3<f64>) -> f64 {
+        let direction = direction.normalize();
+
+        match self {
+            Material::Lambertian(_) => hit_object.normal.dot(&direction).max(0.0) / PI,
+            Material::Metal(_, fuzz) if *fuzz > 0.0 => {
+                if direction.dot(&hit_object.geometric_normal) <= 0.0 {
+                    return 0.0;
+                }
+
+                let reflected = Ray::reflect(r_in.direction.normalize(), hit_object.normal);
+                let exponent = Self::phong_exponent(*fuzz);
+                let cosine = reflected.dot(&direction).max(0.0);
+
+                (exponent + 1.0) / (2.0 * PI) * cosine.powf(exponent)
+            },
+            Material::IsoTropic(_) => 1.0 / (4.0 * PI),
+            _ => 0.0,
+        }
+    }
+
+    // Whether the material only scatters into delta lobes, which light sampling can never produce.
+    pub fn is_delta(&self) -> bool {
+        match self {
+            Material::Metal(_, fuzz) => *fuzz <= 0.0,
+            Material::Dielectric(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn emitted(&self, hit_object: &HitObject) -> Color {
         match self {
             Material::DiffuseLight(emit) => {
-                emit.value(u, v, p)
+                emit.value(hit_object.u, hit_object.v, hit_object.point, &hit_object.differentials)
             },
             _ => Color::new(0.0, 0.0, 0.0),
         }
     }
 
+    // Surface color at the hit point, independent of lighting. White for glass and lights.
+    pub fn albedo(&self, hit_object: &HitObject) -> Color {
+        match self {
+            Material::Lambertian(albedo) | Material::IsoTropic(albedo) => albedo.value(hit_object.u, hit_object.v, hit_object.point, &hit_object.differentials),
+            Material::Metal(albedo, _) => *albedo,
+            Material::Dielectric(_) | Material::DiffuseLight(_) => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::DiffuseLight(_))
+    }
+
+    fn sample_from_direction(&self, r_in: Ray, hit_object: &HitObject, direction: Vector3<f64>) -> Option<MaterialSample> {
+        let pdf = self.pdf(r_in, hit_object, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(MaterialSample {
+            ray: Ray::new(hit_object.point, direction, r_in.time),
+            value: self.eval(r_in, hit_object, direction),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn delta_sample(r_in: Ray, hit_object: &HitObject, direction: Vector3<f64>, attenuation: Color) -> MaterialSample {
+        MaterialSample {
+            ray: Ray::new(hit_object.point, direction, r_in.time),
+            value: attenuation,
+            pdf: 1.0,
+            is_delta: true,
+        }
+    }
+
+    // Phong exponent whose lobe has the same spread as offsetting the reflection by a random point
+    // in a sphere of the fuzz radius: both have a mean squared angle of 0.4 * fuzz^2 = 2 / exponent.
+    // Fuzz is clamped to one as with the sphere, whose offsets would otherwise outgrow the reflection.
+    fn phong_exponent(fuzz: f64) -> f64 {
+        let fuzz = fuzz.min(1.0);
+        5.0 / (fuzz * fuzz)
+    }
+
+    // Direction around the z axis distributed proportionally to cos^exponent.
+    fn random_phong_direction(exponent: f64) -> Vector3<f64> {
+        let (u, v) = random::sample_2d();
+        let cos_theta = u.powf(1.0 / (exponent + 1.0));
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * v;
+
+        Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+    }
+
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -93,12 +215,12 @@ impl Material {
     }
 
     pub fn random_float() -> f64 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         rng.gen()
     }
 
     pub fn random_float_range(range: std::ops::Range<f64>) -> f64 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         rng.gen_range(range)
     }
 }
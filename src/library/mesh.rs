@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+
+use crate::library::basic_lib::*;
+use crate::library::{bvh, hittable::HittableTrait, material::Material, random, triangle::Triangle};
+
+// Faces per leaf, below this count splitting further costs more than testing the faces directly.
+const MAX_LEAF_FACES: usize = 4;
+
+// Size of the traversal stack, which lives on the call stack so rays allocate nothing. A path through
+// the tree never holds more than one pending node per level plus the one being visited.
+const STACK_SIZE: usize = 64;
+
+// Levels built with the surface area heuristic, which may split off few faces at a time. Deeper
+// subtrees are halved by face count, so a u32 face count adds at most 30 more levels.
+const MAX_SAH_DEPTH: usize = 32;
+
+// Vertex attribute buffers, shared between all meshes that index into them.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vector3<f64>>,
+    pub normals: Vec<Vector3<f64>>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+impl MeshData {
+    pub fn face_area(&self, face: &Face) -> f64 {
+        let [p0, p1, p2] = face.vertices.map(|index| self.positions[index as usize]);
+        0.5 * (p1 - p0).cross(&(p2 - p0)).norm()
+    }
+}
+
+// Indices of one triangle into the position, normal and texture coordinate buffers of a mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct Face {
+    pub vertices: [u32; 3],
+    pub normals: Option<[u32; 3]>,
+    pub uvs: Option<[u32; 3]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum MeshNodeKind {
+    // Range of faces in the mesh's face buffer.
+    Leaf { start: u32, count: u32 },
+    // The first child directly follows its parent, only the second child's index is stored.
+    Interior { second_child: u32, axis: u8 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MeshNode {
+    bounding_box: Aabb,
+    kind: MeshNodeKind,
+}
+
+// Indexed triangle mesh sharing a single material between all of its faces.
+// The faces are kept in a flattened bounding volume hierarchy of their own.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    faces: Vec<Face>,
+    nodes: Vec<MeshNode>,
+    material: Arc<Material>,
+    material_id: usize,
+    // Running sums of the face areas in face order, kept only for emissive meshes which are sampled as lights.
+    cumulative_areas: Vec<f64>,
+}
+
+impl TriangleMesh {
+    // Faces of zero area are dropped, they have no normal to shade with and cannot be sampled as lights.
+    pub fn new(data: Arc<MeshData>, mut faces: Vec<Face>, material: impl Into<Arc<Material>>) -> Self {
+        faces.retain(|face| data.face_area(face) > 0.0);
+        assert!(!faces.is_empty(), "cannot build a mesh without faces of non-zero area");
+
+        let mut mesh = TriangleMesh {
+            data,
+            faces: Vec::with_capacity(faces.len()),
+            nodes: Vec::new(),
+            material: material.into(),
+            material_id: 0,
+            cumulative_areas: Vec::new(),
+        };
+        mesh.build_node(faces, 0);
+
+        if mesh.material.is_emissive() {
+            mesh.cumulative_areas = mesh.faces.iter()
+                .scan(0.0, |sum, face| {
+                    *sum += mesh.data.face_area(face);
+                    Some(*sum)
+                })
+                .collect();
+        }
+
+        mesh
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // Index of the material in the scene's material table, handed to every hit.
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
+
+    fn face_bounding_box(&self, face: &Face) -> Aabb {
+        let [p0, p1, p2] = face.vertices.map(|index| self.data.positions[index as usize]);
+        Aabb::new_from_box(Aabb::new_from_point(p0, p1), Aabb::new_from_point(p2, p2)).pad()
+    }
+
+    // Appends the subtree for the given faces and returns the index of its root node.
+    fn build_node(&mut self, mut faces: Vec<Face>, depth: usize) -> usize {
+        let bounding_box = faces.iter().fold(Aabb::empty(), |acc, face| Aabb::new_from_box(acc, self.face_bounding_box(face)));
+
+        let index = self.nodes.len();
+        self.nodes.push(MeshNode { bounding_box, kind: MeshNodeKind::Leaf { start: 0, count: 0 } });
+
+        if faces.len() <= MAX_LEAF_FACES {
+            let start = self.faces.len() as u32;
+            let count = faces.len() as u32;
+            self.faces.extend(faces);
+            self.nodes[index].kind = MeshNodeKind::Leaf { start, count };
+            return index;
+        }
+
+        let (axis, right_faces) = if depth < MAX_SAH_DEPTH {
+            bvh::sah_split(&mut faces, |face| self.face_bounding_box(face))
+        } else {
+            let axis = bounding_box.longest_axis();
+            faces.sort_by(|a, b| {
+                let (a, b) = (self.face_bounding_box(a).centroid()[axis], self.face_bounding_box(b).centroid()[axis]);
+                a.total_cmp(&b)
+            });
+            (axis, faces.split_off(faces.len() / 2))
+        };
+
+        self.build_node(faces, depth + 1);
+        let second_child = self.build_node(right_faces, depth + 1) as u32;
+        self.nodes[index].kind = MeshNodeKind::Interior { second_child, axis: axis as u8 };
+
+        index
+    }
+
+    // Möller–Trumbore intersection, returns the distance and the barycentric coordinates of
+    // the second and third vertex.
+    fn intersect_face(&self, face: &Face, ray: Ray, ray_t: Interval) -> Option<(f64, f64, f64)> {
+        let [p0, p1, p2] = face.vertices.map(|index| self.data.positions[index as usize]);
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let p = ray.direction.cross(&edge2);
+        let determinant = edge1.dot(&p);
+
+        // No hit if the ray is parallel to the triangle.
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let s = ray.origin - p0;
+        let b1 = s.dot(&p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let b2 = ray.direction.dot(&q) * inverse_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) * inverse_determinant;
+        if !ray_t.contains(t) {
+            return None;
+        }
+
+        Some((t, b1, b2))
+    }
+
+    fn hit_object(&self, face: &Face, ray: Ray, t: f64, b1: f64, b2: f64) -> HitObject<'_> {
+        let data = &self.data;
+        let b0 = 1.0 - b1 - b2;
+
+        let [p0, p1, p2] = face.vertices.map(|index| data.positions[index as usize]);
+        let geometric_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+
+        let shading_normal = match face.normals {
+            Some([n0, n1, n2]) => (b0 * data.normals[n0 as usize] + b1 * data.normals[n1 as usize] + b2 * data.normals[n2 as usize]).normalize(),
+            None => geometric_normal,
+        };
+
+        // Interpolate the texture coordinates, or fall back to the barycentric coordinates themselves.
+        let uvs = face.uvs.map(|indices| indices.map(|index| data.uvs[index as usize]));
+        let (u, v) = match uvs {
+            Some([uv0, uv1, uv2]) => (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1),
+            None => (b1, b2),
+        };
+        let (dpdu, dpdv) = Triangle::position_derivatives(p1 - p0, p2 - p0, uvs);
+
+        let hit_point = ray.calculate_hit_position(t);
+        HitObject::new_with_shading_normal(hit_point, ray, geometric_normal, shading_normal, &self.material, t, u, v)
+            .with_differentials(&ray, dpdu, dpdv)
+            .with_material_id(self.material_id)
+    }
+
+    // Visits every face the ray hits within the interval, nearest subtrees first. `visit` returns the
+    // new end of the interval, which lets the closest hit prune everything behind it.
+    fn traverse<'a>(&'a self, ray: Ray, ray_t: Interval, mut visit: impl FnMut(&'a Face, f64, f64, f64) -> f64) {
+        let mut max = ray_t.max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let index = stack[stack_size];
+            let node = &self.nodes[index];
+            if node.bounding_box.hit(ray, Interval::new(ray_t.min, max)).is_none() {
+                continue;
+            }
+
+            match node.kind {
+                MeshNodeKind::Leaf { start, count } => {
+                    for face in &self.faces[start as usize..(start + count) as usize] {
+                        if let Some((t, b1, b2)) = self.intersect_face(face, ray, Interval::new(ray_t.min, max)) {
+                            max = visit(face, t, b1, b2);
+                        }
+                    }
+                },
+                MeshNodeKind::Interior { second_child, axis } => {
+                    // Push the far child first so the near child is visited first.
+                    let (near, far) = if ray.direction[axis as usize] < 0.0 {
+                        (second_child as usize, index + 1)
+                    } else {
+                        (index + 1, second_child as usize)
+                    };
+                    stack[stack_size] = far;
+                    stack[stack_size + 1] = near;
+                    stack_size += 2;
+                },
+            }
+        }
+    }
+}
+
+impl HittableTrait for TriangleMesh {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
+        let mut closest = None;
+        self.traverse(ray, ray_t, |face, t, b1, b2| {
+            closest = Some((face, t, b1, b2));
+            t
+        });
+
+        closest.map(|(face, t, b1, b2)| self.hit_object(face, ray, t, b1, b2))
+    }
+
+    fn get_bounding_box(&self) -> Aabb {
+        self.nodes[0].bounding_box
+    }
+
+    // Points are sampled uniformly over the whole surface, so every face the direction passes through
+    // adds to its density, not only the closest one.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        let Some(&total_area) = self.cumulative_areas.last() else {
+            return 0.0;
+        };
+
+        let mut pdf = 0.0;
+        self.traverse(Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY), |face, t, _, _| {
+            let [p0, p1, p2] = face.vertices.map(|index| self.data.positions[index as usize]);
+            let normal = (p1 - p0).cross(&(p2 - p0));
+
+            // Convert the uniform density over the area into a density over solid angle.
+            let distance_squared = t * t * direction.norm_squared();
+            let cosine = (direction.dot(&normal) / (direction.norm() * normal.norm())).abs();
+            // Grazing directions see none of the face's area.
+            if cosine > 0.0 {
+                pdf += distance_squared / (cosine * total_area);
+            }
+
+            f64::INFINITY
+        });
+
+        pdf
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        let Some(&total_area) = self.cumulative_areas.last() else {
+            return Vector3::new(1.0, 0.0, 0.0);
+        };
+
+        // The first number picks a face by area and is then stretched back over [0, 1), so the
+        // point on the face needs no extra dimension.
+        let (picked, mut beta) = random::sample_2d();
+        let target = picked * total_area;
+        let index = self.cumulative_areas.partition_point(|&sum| sum <= target).min(self.faces.len() - 1);
+        let start = if index == 0 { 0.0 } else { self.cumulative_areas[index - 1] };
+        let mut alpha = ((target - start) / (self.cumulative_areas[index] - start)).clamp(0.0, 1.0);
+
+        // Fold points from the far half of the parallelogram back into the triangle.
+        if alpha + beta > 1.0 {
+            (alpha, beta) = (1.0 - alpha, 1.0 - beta);
+        }
+
+        let [p0, p1, p2] = self.faces[index].vertices.map(|index| self.data.positions[index as usize]);
+        p0 + alpha * (p1 - p0) + beta * (p2 - p0) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{color::Color, sampler::Sampler, texture::Texture};
+
+    fn face(vertices: [u32; 3]) -> Face {
+        Face { vertices, normals: None, uvs: None }
+    }
+
+    #[test]
+    fn zero_area_faces_are_dropped() {
+        let data = MeshData {
+            positions: vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(2.0, 0.0, 0.0)],
+            ..MeshData::default()
+        };
+        // A repeated corner and three collinear corners around the one proper triangle.
+        let faces = vec![face([0, 1, 1]), face([0, 1, 2]), face([0, 1, 3])];
+        let light = Material::DiffuseLight(Texture::SolidColor(Color::new(1.0, 1.0, 1.0)));
+        let mesh = TriangleMesh::new(Arc::new(data), faces, light);
+
+        assert_eq!(mesh.faces.len(), 1);
+        assert_eq!(mesh.cumulative_areas, vec![0.5]);
+
+        let origin = Vector3::new(0.25, 1.0, 0.25);
+        for index in 0..64 {
+            random::start_sample(Sampler::Sobol, 0, 0, index);
+            let direction = mesh.random(origin);
+            let pdf = mesh.pdf_value(origin, direction);
+
+            assert!(direction.iter().all(|component| component.is_finite()), "{:?}", direction);
+            assert!(pdf.is_finite() && pdf > 0.0, "{}", pdf);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod render;
+#[cfg(feature = "viewer")]
 pub mod check_input;
 pub mod sphere;
 pub mod ray;
@@ -6,7 +7,9 @@ pub mod color;
 pub mod constants;
 pub mod scene;
 pub mod hit_object;
+#[cfg(feature = "viewer")]
 pub mod draw;
+#[cfg(feature = "viewer")]
 pub mod fps_counter;
 pub mod material;
 pub mod vector3;
@@ -18,6 +21,7 @@ pub mod hittable;
 pub mod bvh;
 pub mod texture;
 pub mod image;
+pub mod image_texture;
 pub mod perlin;
 pub mod basic_lib;
 pub mod quad;
@@ -26,3 +30,17 @@ pub mod rotate_y;
 pub mod constant_medium;
 pub mod quadbox;
 pub mod triangle;
+pub mod onb;
+pub mod cli;
+pub mod output;
+pub mod scene_file;
+pub mod mesh;
+pub mod obj;
+pub mod renderer;
+pub mod aov;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod random;
+pub mod sampler;
+pub mod tone_mapping;
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+
+use crate::library::{color::Color, image::{ColorSpace, Image, ImageCache}, image_texture::ImageTexture, material::Material, mesh::{Face, MeshData, TriangleMesh}, texture::Texture};
+
+// Error raised while reading an OBJ or MTL file.
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Material properties as read from an MTL file, before they are mapped onto a `Material`.
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: Option<f64>,
+    illumination: Option<u32>,
+    diffuse_map: Option<PathBuf>,
+    specular_map: Option<PathBuf>,
+    emission_map: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            refraction_index: None,
+            illumination: None,
+            diffuse_map: None,
+            specular_map: None,
+            emission_map: None,
+        }
+    }
+}
+
+// Loads a Wavefront OBJ file, returning one triangle mesh per material used in the file.
+// Materials come from the referenced MTL libraries unless `material_override` is given, their
+// textures are loaded through `images`.
+pub fn load_obj<P: AsRef<Path>>(path: P, material_override: Option<Arc<Material>>, images: &mut ImageCache) -> Result<Vec<TriangleMesh>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let error = |line: usize, message: String| ObjError { path: path.to_path_buf(), line: Some(line), message };
+
+    let mut data = MeshData::default();
+
+    let mut library: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut group_names: Vec<Option<String>> = vec![None];
+    let mut groups: Vec<Vec<Face>> = vec![Vec::new()];
+    let mut current_group = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => data.positions.push(parse_vector(tokens).map_err(|message| error(line_number, message))?),
+            Some("vn") => data.normals.push(parse_vector(tokens).map_err(|message| error(line_number, message))?),
+            Some("vt") => {
+                let u = parse_float(tokens.next()).map_err(|message| error(line_number, message))?;
+                let v = tokens.next().map_or(Ok(0.0), |value| parse_float(Some(value))).map_err(|message| error(line_number, message))?;
+                data.uvs.push((u, v));
+            },
+            Some("f") => {
+                let corners = tokens
+                    .map(|token| parse_corner(token, data.positions.len(), data.uvs.len(), data.normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|message| error(line_number, message))?;
+
+                if corners.len() < 3 {
+                    return Err(error(line_number, String::from("a face needs at least three vertices")));
+                }
+
+                // Triangulate polygons as a fan around the first corner.
+                for i in 1..corners.len() - 1 {
+                    let triangle = [corners[0], corners[i], corners[i + 1]];
+
+                    let face = Face {
+                        vertices: triangle.map(|corner| corner.0),
+                        uvs: triangle.iter().map(|corner| corner.1).collect::<Option<Vec<_>>>().map(|uv| [uv[0], uv[1], uv[2]]),
+                        normals: triangle.iter().map(|corner| corner.2).collect::<Option<Vec<_>>>().map(|n| [n[0], n[1], n[2]]),
+                    };
+
+                    // Triangles of zero area, e.g. from repeated or collinear corners, cover nothing.
+                    if data.face_area(&face) > 0.0 {
+                        groups[current_group].push(face);
+                    }
+                }
+            },
+            // Material libraries are not read at all when the caller overrides the material.
+            Some("mtllib") if material_override.is_none() => {
+                for file in tokens {
+                    library.extend(load_mtl(&directory.join(file))?);
+                }
+            },
+            Some("usemtl") => {
+                let name = tokens.next().map(str::to_string);
+                if material_override.is_none() {
+                    if let Some(name) = &name {
+                        if !library.contains_key(name) {
+                            return Err(error(line_number, format!("unknown material '{}'", name)));
+                        }
+                    }
+                }
+
+                current_group = match group_names.iter().position(|group| *group == name) {
+                    Some(group) => group,
+                    None => {
+                        group_names.push(name);
+                        groups.push(Vec::new());
+                        groups.len() - 1
+                    },
+                };
+            },
+            _ => {},
+        }
+    }
+
+    let data = Arc::new(data);
+    let mut meshes = Vec::new();
+
+    for (name, faces) in group_names.into_iter().zip(groups) {
+        if faces.is_empty() {
+            continue;
+        }
+
+        let material = match (&material_override, name) {
+            (Some(material), _) => material.clone(),
+            (None, Some(name)) => Arc::new(to_material(&library[&name], images)?),
+            (None, None) => Arc::new(to_material(&MtlMaterial::default(), images)?),
+        };
+
+        meshes.push(TriangleMesh::new(data.clone(), faces, material));
+    }
+
+    if meshes.is_empty() {
+        return Err(ObjError { path: path.to_path_buf(), line: None, message: String::from("the file contains no faces") });
+    }
+
+    Ok(meshes)
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let source = read_file(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let error = |line: usize, message: String| ObjError { path: path.to_path_buf(), line: Some(line), message };
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+
+            let name = tokens.next().ok_or(error(line_number, String::from("missing material name")))?;
+            current = Some((name.to_string(), MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            continue;
+        };
+
+        let result = match keyword {
+            "Kd" => parse_color(tokens).map(|color| material.diffuse = color),
+            "Ks" => parse_color(tokens).map(|color| material.specular = color),
+            "Ke" => parse_color(tokens).map(|color| material.emission = color),
+            "Ns" => parse_float(tokens.next()).map(|value| material.shininess = value),
+            "Ni" => parse_float(tokens.next()).map(|value| material.refraction_index = Some(value)),
+            "illum" => parse_float(tokens.next()).map(|value| material.illumination = Some(value as u32)),
+            // Map statements may carry options before the file name, which always comes last.
+            "map_Kd" => tokens.last().ok_or(String::from("missing texture file")).map(|file| material.diffuse_map = Some(directory.join(file))),
+            "map_Ks" => tokens.last().ok_or(String::from("missing texture file")).map(|file| material.specular_map = Some(directory.join(file))),
+            "map_Ke" => tokens.last().ok_or(String::from("missing texture file")).map(|file| material.emission_map = Some(directory.join(file))),
+            _ => Ok(()),
+        };
+
+        result.map_err(|message| error(line_number, message))?;
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+// Picks the material variant for the MTL description. Emissive materials become lights, the others
+// follow their illumination model: 3 is a mirror, 4, 6, 7 and 9 are transparent and everything else is diffuse.
+fn to_material(mtl: &MtlMaterial, images: &mut ImageCache) -> Result<Material, ObjError> {
+    if max_component(mtl.emission) > 0.0 {
+        // The emission map scales the emission color.
+        let emit = match &mtl.emission_map {
+            Some(path) => Texture::Image(ImageTexture { tint: mtl.emission, ..ImageTexture::new(load_image(path, images)?) }),
+            None => Texture::SolidColor(mtl.emission),
+        };
+        return Ok(Material::DiffuseLight(emit));
+    }
+
+    match mtl.illumination {
+        Some(3) => {
+            // Metals have a single color, a specular map contributes its average.
+            let specular = match &mtl.specular_map {
+                Some(path) => average_color(path, images)?,
+                None => mtl.specular,
+            };
+            // Convert the Phong exponent into the fuzz radius of the same lobe, see `Material::phong_exponent`.
+            let fuzz = (5.0 / mtl.shininess.max(1.0)).sqrt().clamp(0.0, 1.0);
+            Ok(Material::Metal(specular, fuzz))
+        },
+        Some(4 | 6 | 7 | 9) => Ok(Material::Dielectric(mtl.refraction_index.unwrap_or(1.5))),
+        _ => Ok(Material::Lambertian(texture(mtl.diffuse_map.as_ref(), mtl.diffuse, images)?)),
+    }
+}
+
+fn texture(map: Option<&PathBuf>, color: Color, images: &mut ImageCache) -> Result<Texture, ObjError> {
+    let Some(path) = map else {
+        return Ok(Texture::SolidColor(color));
+    };
+
+    Ok(Texture::Image(ImageTexture::new(load_image(path, images)?)))
+}
+
+// The last mipmap level is the single pixel average of the whole image.
+fn average_color(path: &Path, images: &mut ImageCache) -> Result<Color, ObjError> {
+    let image = load_image(path, images)?;
+    Ok(image.levels().last().map_or(Color::new(0.0, 0.0, 0.0), |level| level.pixel(0, 0)))
+}
+
+fn load_image(path: &Path, images: &mut ImageCache) -> Result<Image, ObjError> {
+    images.load(path, ColorSpace::Srgb).map_err(|error| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        message: format!("failed to load texture: {}", error),
+    })
+}
+
+// Parses a `v/vt/vn` face corner into zero-based indices.
+fn parse_corner(token: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<(u32, Option<u32>, Option<u32>), String> {
+    let mut parts = token.split('/');
+
+    let vertex = resolve_index(parts.next(), position_count)?.ok_or(format!("missing vertex index in '{}'", token))?;
+    let uv = resolve_index(parts.next(), uv_count)?;
+    let normal = resolve_index(parts.next(), normal_count)?;
+
+    Ok((vertex, uv, normal))
+}
+
+// OBJ indices are one-based, negative indices count backwards from the last element read so far.
+fn resolve_index(token: Option<&str>, count: usize) -> Result<Option<u32>, String> {
+    let Some(token) = token.filter(|token| !token.is_empty()) else {
+        return Ok(None);
+    };
+
+    let index: i64 = token.parse().map_err(|_| format!("invalid index '{}'", token))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} is out of range", index));
+    }
+
+    Ok(Some(resolved as u32))
+}
+
+fn parse_float(token: Option<&str>) -> Result<f64, String> {
+    let token = token.ok_or(String::from("missing value"))?;
+    token.parse().map_err(|_| format!("invalid number '{}'", token))
+}
+
+fn parse_vector<'a, I: Iterator<Item = &'a str>>(mut tokens: I) -> Result<Vector3<f64>, String> {
+    Ok(Vector3::new(parse_float(tokens.next())?, parse_float(tokens.next())?, parse_float(tokens.next())?))
+}
+
+fn parse_color<'a, I: Iterator<Item = &'a str>>(tokens: I) -> Result<Color, String> {
+    let v = parse_vector(tokens)?;
+    Ok(Color::new(v.x, v.y, v.z))
+}
+
+fn max_component(color: Color) -> f64 {
+    color.r.max(color.g).max(color.b)
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|error| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        message: error.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::hit_object::SurfaceDifferentials;
+
+    #[test]
+    fn indices_are_one_based_or_relative_to_the_end() {
+        assert_eq!(resolve_index(Some("1"), 4), Ok(Some(0)));
+        assert_eq!(resolve_index(Some("4"), 4), Ok(Some(3)));
+        assert_eq!(resolve_index(Some("-1"), 4), Ok(Some(3)));
+        assert_eq!(resolve_index(Some("-4"), 4), Ok(Some(0)));
+        assert_eq!(resolve_index(Some(""), 4), Ok(None));
+        assert_eq!(resolve_index(None, 4), Ok(None));
+
+        for index in ["0", "5", "-5", "x"] {
+            assert!(resolve_index(Some(index), 4).is_err(), "{}", index);
+        }
+    }
+
+    #[test]
+    fn corners_may_leave_out_texture_coordinates_and_normals() {
+        assert_eq!(parse_corner("3/2/1", 3, 3, 3), Ok((2, Some(1), Some(0))));
+        assert_eq!(parse_corner("3//-1", 3, 0, 3), Ok((2, None, Some(2))));
+        assert_eq!(parse_corner("-2/1", 3, 1, 0), Ok((1, Some(0), None)));
+        assert_eq!(parse_corner("2", 3, 0, 0), Ok((1, None, None)));
+        assert!(parse_corner("/1/1", 3, 3, 3).is_err());
+        assert!(parse_corner("1/2", 3, 1, 0).is_err());
+    }
+
+    fn material(mtl: MtlMaterial) -> Material {
+        to_material(&mtl, &mut ImageCache::new()).unwrap()
+    }
+
+    #[test]
+    fn illumination_model_3_is_a_mirror() {
+        let specular = Color::new(0.8, 0.8, 0.85);
+        let mtl = MtlMaterial { specular, shininess: 20.0, illumination: Some(3), ..MtlMaterial::default() };
+
+        assert_eq!(material(mtl), Material::Metal(specular, 0.5));
+    }
+
+    #[test]
+    fn transparent_illumination_models_are_dielectrics() {
+        for illumination in [4, 6, 7, 9] {
+            let mtl = MtlMaterial { refraction_index: Some(1.33), illumination: Some(illumination), ..MtlMaterial::default() };
+            assert_eq!(material(mtl), Material::Dielectric(1.33), "illum {}", illumination);
+        }
+
+        let mtl = MtlMaterial { illumination: Some(7), ..MtlMaterial::default() };
+        assert_eq!(material(mtl), Material::Dielectric(1.5));
+    }
+
+    #[test]
+    fn other_illumination_models_are_diffuse() {
+        let diffuse = Color::new(0.2, 0.4, 0.6);
+        for illumination in [None, Some(0), Some(1), Some(2), Some(5), Some(8), Some(10)] {
+            // A bright specular color alone does not make a metal.
+            let mtl = MtlMaterial { diffuse, specular: Color::new(1.0, 1.0, 1.0), illumination, ..MtlMaterial::default() };
+            assert_eq!(material(mtl), Material::Lambertian(Texture::SolidColor(diffuse)), "illum {:?}", illumination);
+        }
+    }
+
+    #[test]
+    fn emission_color_makes_a_light() {
+        let emission = Color::new(4.0, 4.0, 3.0);
+        let mtl = MtlMaterial { emission, illumination: Some(3), ..MtlMaterial::default() };
+
+        assert_eq!(material(mtl), Material::DiffuseLight(Texture::SolidColor(emission)));
+    }
+
+    #[test]
+    fn emission_map_is_scaled_by_the_emission_color() {
+        let path = PathBuf::from("assets/earth_200.jpg");
+        let emission = Color::new(2.0, 3.0, 4.0);
+        let mut images = ImageCache::new();
+        let plain = ImageTexture::new(load_image(&path, &mut images).unwrap());
+
+        let mtl = MtlMaterial { emission, emission_map: Some(path), ..MtlMaterial::default() };
+        let Ok(Material::DiffuseLight(Texture::Image(texture))) = to_material(&mtl, &mut images) else {
+            panic!("not an emissive image texture");
+        };
+
+        let differentials = SurfaceDifferentials::default();
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            assert_eq!(texture.value(u, v, &differentials), emission * plain.value(u, v, &differentials));
+        }
+
+        // Without an emission color the map stays dark.
+        let mtl = MtlMaterial { emission_map: mtl.emission_map.clone(), ..MtlMaterial::default() };
+        assert!(matches!(to_material(&mtl, &mut images), Ok(Material::Lambertian(_))));
+    }
+}
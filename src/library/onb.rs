@@ -0,0 +1,24 @@
+use nalgebra::Vector3;
+
+// Orthonormal basis around a direction, used to place locally generated directions in world space.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Onb {
+    pub fn new(n: Vector3<f64>) -> Self {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).normalize();
+        let u = w.cross(&v);
+
+        Onb { u, v, w }
+    }
+
+    pub fn transform(&self, a: Vector3<f64>) -> Vector3<f64> {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, WritableImage};
+use image::codecs::hdr::HdrEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ImageError, ImageFormat, ImageResult, Rgb, RgbImage};
+
+use crate::library::{aov::Aov, color::Color, renderer::Framebuffer, tone_mapping::DisplayTransform};
+
+// Writes the framebuffer to disk, the format is picked from the file extension.
+// `.exr`, `.hdr` and `.pfm` keep the linear colors unclamped, any other format gets an 8-bit image
+// made with the display transform.
+// Output variables become extra channels of an OpenEXR file, other formats write each of them to
+// its own file next to the image, e.g. `render.albedo.png` for `render.png`.
+pub fn save_image(path: &str, framebuffer: &Framebuffer, display: &DisplayTransform) -> ImageResult<()> {
+    let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+
+    if extension.as_deref() == Some("exr") {
+        return save_exr(path, framebuffer);
+    }
+
+    write_image(Path::new(path), extension.as_deref(), framebuffer.width, framebuffer.height, &framebuffer.pixels, None, display)?;
+
+    for (aov, pixels) in &framebuffer.aovs {
+        let aov_path = aov_path(path, *aov);
+        write_image(&aov_path, extension.as_deref(), framebuffer.width, framebuffer.height, pixels, Some(*aov), display)?;
+    }
+
+    Ok(())
+}
+
+// `render.png` becomes `render.<aov>.png`.
+fn aov_path(path: &str, aov: Aov) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+
+    let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}.{}.{}", stem, aov.name(), extension),
+        None => format!("{}.{}", stem, aov.name()),
+    };
+
+    path.with_file_name(file_name)
+}
+
+fn write_image(path: &Path, extension: Option<&str>, width: usize, height: usize, pixels: &[Color], aov: Option<Aov>, display: &DisplayTransform) -> ImageResult<()> {
+    match extension {
+        Some("hdr") => save_hdr(path, width, height, pixels),
+        Some("pfm") => save_pfm(path, width, height, pixels),
+        _ => save_ldr(path, width, height, &display_colors(pixels, aov, display)),
+    }
+}
+
+// Maps linear values into the displayable range: the image goes through the display transform,
+// colors of output variables are only sRGB encoded, normals are moved from [-1, 1] into [0, 1] and
+// the remaining variables are stretched over the range they cover.
+fn display_colors(pixels: &[Color], aov: Option<Aov>, display: &DisplayTransform) -> Vec<Color> {
+    match aov {
+        None => pixels.iter().map(|color| display.apply(*color)).collect(),
+        Some(Aov::Albedo) | Some(Aov::Emission) => pixels.iter().map(|color| DisplayTransform::default().apply(*color)).collect(),
+        Some(Aov::Normal) => pixels.iter().map(|normal| ((*normal + Color::new(1.0, 1.0, 1.0)) * 0.5).clamp()).collect(),
+        Some(Aov::Depth) | Some(Aov::Position) | Some(Aov::ObjectId) | Some(Aov::MaterialId) => {
+            let min = pixels.iter().fold(Color::new(f64::INFINITY, f64::INFINITY, f64::INFINITY), |acc, color| {
+                Color::new(acc.r.min(color.r), acc.g.min(color.g), acc.b.min(color.b))
+            });
+            let max = pixels.iter().fold(Color::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY), |acc, color| {
+                Color::new(acc.r.max(color.r), acc.g.max(color.g), acc.b.max(color.b))
+            });
+            let normalize = |value: f64, min: f64, max: f64| if max > min { (value - min) / (max - min) } else { 0.0 };
+
+            pixels.iter()
+                .map(|color| Color::new(normalize(color.r, min.r, max.r), normalize(color.g, min.g, max.g), normalize(color.b, min.b, max.b)).clamp())
+                .collect()
+        },
+    }
+}
+
+fn save_ldr(path: &Path, width: usize, height: usize, display_colors: &[Color]) -> ImageResult<()> {
+    let mut image = RgbImage::new(width as u32, height as u32);
+
+    for (pixel, color) in image.pixels_mut().zip(display_colors.iter()) {
+        pixel.0 = color.to_rgb8();
+    }
+
+    image.save(path)
+}
+
+// OpenEXR with 32-bit float channels, holding the image as `R`, `G` and `B` and every output
+// variable under the channel names given by `Aov::channels`.
+fn save_exr(path: &str, framebuffer: &Framebuffer) -> ImageResult<()> {
+    let channel = |name: &str, pixels: &[Color], component: fn(&Color) -> f64| {
+        AnyChannel::new(name, FlatSamples::F32(pixels.iter().map(|color| component(color) as f32).collect()))
+    };
+    let components: [fn(&Color) -> f64; 3] = [|color| color.r, |color| color.g, |color| color.b];
+
+    let mut channels = Vec::new();
+    for (name, component) in ["R", "G", "B"].into_iter().zip(components) {
+        channels.push(channel(name, &framebuffer.pixels, component));
+    }
+
+    for (aov, pixels) in &framebuffer.aovs {
+        for (name, component) in aov.channels().iter().zip(components) {
+            channels.push(channel(name, pixels, component));
+        }
+    }
+
+    let layer = Layer::new(
+        (framebuffer.width, framebuffer.height),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(channels.into()),
+    );
+
+    Image::from_layer(layer).write().to_file(path).map_err(|error| {
+        ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::OpenExr), error))
+    })
+}
+
+// Radiance RGBE, which shares one exponent between the three channels and cannot store negative values.
+fn save_hdr(path: &Path, width: usize, height: usize, pixels: &[Color]) -> ImageResult<()> {
+    let pixels: Vec<Rgb<f32>> = pixels.iter()
+        .map(|color| Rgb([color.r.max(0.0) as f32, color.g.max(0.0) as f32, color.b.max(0.0) as f32]))
+        .collect();
+
+    let writer = BufWriter::new(File::create(path).map_err(ImageError::IoError)?);
+    HdrEncoder::new(writer).encode(&pixels, width, height)
+}
+
+// Portable float map: a short text header followed by little-endian floats, with rows stored bottom to top.
+fn save_pfm(path: &Path, width: usize, height: usize, pixels: &[Color]) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path).map_err(ImageError::IoError)?);
+
+    // A negative scale marks the data as little-endian.
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height).map_err(ImageError::IoError)?;
+
+    for row in pixels.chunks(width).rev() {
+        for color in row {
+            for channel in [color.r, color.g, color.b] {
+                writer.write_all(&(channel as f32).to_le_bytes()).map_err(ImageError::IoError)?;
+            }
+        }
+    }
+
+    writer.flush().map_err(ImageError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pfm_stores_little_endian_rows_bottom_to_top() {
+        let path = std::env::temp_dir().join(format!("raytracer-test-{}.pfm", std::process::id()));
+        let pixels = [Color::new(1.0, 2.0, 3.0), Color::new(-4.0, 0.5, 0.0), Color::new(7.0, 8.0, 9.0), Color::new(10.0, 11.0, 12.0)];
+
+        save_pfm(&path, 2, 2, &pixels).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let values: Vec<f32> = bytes[header.len()..].chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect();
+        assert_eq!(values, [7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 1.0, 2.0, 3.0, -4.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn aov_files_sit_next_to_the_image() {
+        assert_eq!(aov_path("out/render.png", Aov::Albedo), PathBuf::from("out/render.albedo.png"));
+        assert_eq!(aov_path("render", Aov::ObjectId), PathBuf::from("render.object_id"));
+    }
+}
@@ -1,14 +1,30 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 use rand::prelude::*;
 
-use crate::library::vector3::Vector3Extensions;
+use crate::library::{random, vector3::Vector3Extensions};
 
+// The tables are shared between clones, so textures using the same noise can be copied freely.
 #[derive(Debug, Clone)]
 pub struct Perlin {
-    random_vector: Vec<Vector3<f64>>,
-    perm_x: Vec<usize>,
-    perm_y: Vec<usize>,
-    perm_z: Vec<usize>,
+    random_vector: Arc<[Vector3<f64>]>,
+    perm_x: Arc<[usize]>,
+    perm_y: Arc<[usize]>,
+    perm_z: Arc<[usize]>,
+}
+
+// Noise is the same when it shares its tables, i.e. for clones of one `Perlin`.
+impl PartialEq for Perlin {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.random_vector, &other.random_vector)
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Perlin {
@@ -22,10 +38,10 @@ impl Perlin {
         let perm_z = Self::perlin_generate_perm(point_count);
 
         Perlin {
-            random_vector,
-            perm_x,
-            perm_y,
-            perm_z,
+            random_vector: random_vector.into(),
+            perm_x: perm_x.into(),
+            perm_y: perm_y.into(),
+            perm_z: perm_z.into(),
         }
     }
 
@@ -69,12 +85,12 @@ impl Perlin {
     }
 
     fn perlin_generate_perm(point_count: usize) -> Vec<usize> {
-        let mut p = (0..point_count).collect();
+        let mut p: Vec<usize> = (0..point_count).collect();
         Self::permute(&mut p);
         p
     }
 
-    fn permute(p: &mut Vec<usize>) {
+    fn permute(p: &mut [usize]) {
         for i in (1..p.len()).rev() {
             let target = Self::random_usize(0, i);
 
@@ -89,15 +105,15 @@ impl Perlin {
         
         let mut accumulate = 0.0;
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
+        for (i, plane) in c.iter().enumerate() {
+            for (j, line) in plane.iter().enumerate() {
+                for (k, vector) in line.iter().enumerate() {
                     let weight_v = Vector3::new(u-i as f64, v-j as f64, w-k as f64);
 
                     accumulate += (i as f64 * uu + (1.0-i as f64) * (1.0-uu)) *
                                   (j as f64 * vv + (1.0-j as f64) * (1.0-vv)) *
                                   (k as f64 * ww + (1.0-k as f64) * (1.0-ww)) *
-                                  c[i][j][k].dot(&weight_v);
+                                  vector.dot(&weight_v);
                 }
             }
         }
@@ -106,7 +122,7 @@ impl Perlin {
     }
 
     fn random_usize(min: usize, max: usize) -> usize{
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         rng.gen_range(min..=max)
     }
 }
\ No newline at end of file
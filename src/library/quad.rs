@@ -1,22 +1,26 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 
 use crate::library::basic_lib::*;
-use crate::library::{hittable::HittableTrait, material::Material};
+use crate::library::{hittable::HittableTrait, material::Material, random, vector3::Vector3Extensions};
 
 #[derive(Debug, Clone)]
 pub struct Quad {
     q: Vector3<f64>, // Lower-left corner
     u: Vector3<f64>, // first side
     v: Vector3<f64>, // second side
-    material: Material,
+    material: Arc<Material>,
+    material_id: usize,
     bounding_box: Aabb,
     normal: Vector3<f64>,
     d: f64,          // a constant
     w: Vector3<f64>,
+    area: f64,
 }
 
 impl Quad {
-    pub fn new(q: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>, material: Material) -> Self {
+    pub fn new(q: Vector3<f64>, u: Vector3<f64>, v: Vector3<f64>, material: impl Into<Arc<Material>>) -> Self {
         let n = u.cross(&v);
         let normal = n.normalize();
         let d = normal.dot(&q);
@@ -24,17 +28,28 @@ impl Quad {
 
         Quad {
             q, u, v,
-            material,
+            material: material.into(),
+            material_id: 0,
             bounding_box: Aabb::new_from_point(q, q + u + v).pad(),
             normal, d, w,
+            area: n.norm(),
         }
     }
 
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // Index of the material in the scene's material table, handed to every hit.
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
+
     fn is_interior(a: f64, b: f64) -> Option<(f64, f64)> {
         // Given the hit point in plane coordinates, return false if it is outside the
         // primitive, otherwise set the hit record UV coordinates and return true.
 
-        if (a < 0.0) || (1.0 < a) || (b < 0.0) || (1.0 < b) {
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
             return None;
         }
 
@@ -43,7 +58,7 @@ impl Quad {
 }
 
 impl HittableTrait for Quad {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         let denomenator = self.normal.dot(&ray.direction);
 
         // No hit if the ray is parallel to the plane.
@@ -66,7 +81,7 @@ impl HittableTrait for Quad {
         if let Some((u, v)) = Self::is_interior(alpha, beta) {
             // Ray hits the 2D shape; set the rest of the hit record and return true.
             let hit_point = ray.calculate_hit_position(t);
-            let hit_object = HitObject::new(hit_point, ray, self.normal, self.material.clone(), t, u, v);
+            let hit_object = HitObject::new(hit_point, ray, self.normal, &self.material, t, u, v).with_differentials(&ray, self.u, self.v).with_material_id(self.material_id);
 
             Some(hit_object)
         } else {
@@ -77,4 +92,22 @@ impl HittableTrait for Quad {
     fn get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
+
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        let Some(hit_object) = self.hit(Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        // Convert the uniform density over the area into a density over solid angle.
+        let distance_squared = hit_object.t * hit_object.t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length_squared().sqrt()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        let (alpha, beta) = random::sample_2d();
+        let p = self.q + alpha * self.u + beta * self.v;
+        p - origin
+    }
 }
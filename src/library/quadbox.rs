@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 
 use crate::library::basic_lib::*;
@@ -6,11 +8,14 @@ use crate::library::{hittable::{Hittable, HittableTrait}, material::Material, qu
 #[derive(Debug, Clone)]
 pub struct Quadbox {
     pub objects: Vec<Hittable>,
+    bounding_box: Aabb,
 }
 
 impl Quadbox {
     // Returns the 3D box (six sides) that contains the two opposite vertices a & b.
-    pub fn new(a: Vector3<f64>, b: Vector3<f64>, material: Material) -> Self {
+    pub fn new(a: Vector3<f64>, b: Vector3<f64>, material: impl Into<Arc<Material>>) -> Self {
+        let material: Arc<Material> = material.into();
+
         let mut objects = vec![];
 
         // Construct the two opposite vertices with the minimum and maximum coordinates.
@@ -44,12 +49,16 @@ impl Quadbox {
         objects.push(Hittable::Quad(top));
         objects.push(Hittable::Quad(bottom));
 
-        Self { objects }
+        let bounding_box = Aabb::new_from_point(min, max).pad();
+
+        Self { objects, bounding_box }
     }
 }
 
 impl HittableTrait for Quadbox {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
+        self.bounding_box.hit(ray, ray_t)?;
+
         let (_closest, hit_record) = self.objects.iter().fold(
             (ray_t.max, None),
             |acc, item| {
@@ -67,6 +76,6 @@ impl HittableTrait for Quadbox {
     }
 
     fn get_bounding_box(&self) -> Aabb {
-        Aabb::new(Interval::universe(), Interval::universe(), Interval::universe())
+        self.bounding_box
     }
 }
@@ -0,0 +1,95 @@
+use std::cell::{Cell, RefCell};
+
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use crate::library::sampler::Sampler;
+
+// Seed of the scene builders and of renders that do not pick their own.
+pub const DEFAULT_SEED: u64 = 0;
+
+// The camera sample being traced on this thread, and the next of its dimensions to hand out.
+#[derive(Debug, Clone, Copy)]
+struct SampleState {
+    sampler: Sampler,
+    key: u64,
+    index: u64,
+    dimension: usize,
+}
+
+const NO_SAMPLE: SampleState = SampleState { sampler: Sampler::Independent, key: 0, index: 0, dimension: 0 };
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(DEFAULT_SEED));
+    static SAMPLE: Cell<SampleState> = const { Cell::new(NO_SAMPLE) };
+}
+
+// Handle to the current thread's seeded generator, used wherever `rand::thread_rng` would be.
+#[derive(Debug, Clone, Copy)]
+pub struct SeededRng;
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+pub fn rng() -> SeededRng {
+    SeededRng
+}
+
+// Restarts the current thread's sequence from `seed`, outside of any camera sample.
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+    SAMPLE.set(NO_SAMPLE);
+}
+
+// Starts one camera sample. Its numbers depend only on the render seed, the pixel and the sample's
+// index within that pixel, so an image comes out the same however rayon spreads the rows over its threads.
+pub fn start_sample(sampler: Sampler, seed: u64, pixel_index: usize, sample_index: usize) {
+    let key = mix(seed ^ mix(pixel_index as u64));
+
+    reseed(mix(key ^ mix(sample_index as u64)));
+    SAMPLE.set(SampleState { sampler, key, index: sample_index as u64, dimension: 0 });
+}
+
+// Moves to the given dimension of the current sample, so every bounce uses the same dimensions
+// however many the previous ones took.
+pub fn set_dimension(dimension: usize) {
+    SAMPLE.set(SampleState { dimension, ..SAMPLE.get() });
+}
+
+// Next dimension of the current sample, a uniform random number outside of camera samples.
+pub fn sample_1d() -> f64 {
+    let state = SAMPLE.get();
+    SAMPLE.set(SampleState { dimension: state.dimension + 1, ..state });
+
+    state.sampler.get_1d(state.key, state.index, state.dimension)
+}
+
+// Next pair of dimensions of the current sample.
+pub fn sample_2d() -> (f64, f64) {
+    let state = SAMPLE.get();
+    SAMPLE.set(SampleState { dimension: state.dimension + 2, ..state });
+
+    state.sampler.get_2d(state.key, state.index, state.dimension)
+}
+
+// SplitMix64 finalizer, spreads neighbouring inputs over unrelated outputs.
+pub fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
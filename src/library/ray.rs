@@ -1,46 +1,80 @@
 use nalgebra::Vector3;
-use rand::prelude::*;
 
-use crate::library::vector3::Vector3Extensions;
+use crate::library::{random, vector3::Vector3Extensions};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vector3<f64>,
     pub direction: Vector3<f64>,
     pub time: f64,
+    // Only camera rays carry differentials, every other ray is treated as infinitely thin.
+    pub differentials: Option<RayDifferentials>,
+}
+
+// Rays offset from the ray towards the neighbouring pixels to the right (x) and below (y), which tell
+// how much of a surface the pixel, or the sample's share of it, covers wherever the ray hits.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferentials {
+    pub x_origin: Vector3<f64>,
+    pub x_direction: Vector3<f64>,
+    pub y_origin: Vector3<f64>,
+    pub y_direction: Vector3<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Vector3<f64>, direction: Vector3<f64>, time: f64) -> Self {
-        Ray { origin, direction, time }
+        Ray { origin, direction, time, differentials: None }
+    }
+
+    pub fn with_differentials(self, differentials: RayDifferentials) -> Self {
+        Ray { differentials: Some(differentials), ..self }
+    }
+
+    // The ray moved into another space, with `point` mapping positions and `vector` mapping directions.
+    pub fn transform(&self, point: impl Fn(Vector3<f64>) -> Vector3<f64>, vector: impl Fn(Vector3<f64>) -> Vector3<f64>) -> Self {
+        Ray {
+            origin: point(self.origin),
+            direction: vector(self.direction),
+            time: self.time,
+            differentials: self.differentials.map(|differentials| RayDifferentials {
+                x_origin: point(differentials.x_origin),
+                x_direction: vector(differentials.x_direction),
+                y_origin: point(differentials.y_origin),
+                y_direction: vector(differentials.y_direction),
+            }),
+        }
     }
-    
+
     pub fn calculate_hit_position(&self, distance: f64) -> Vector3<f64> {
         self.origin + distance * self.direction
     }
 
+    // Uniformly distributed direction, taken from the next two dimensions of the current sample.
     pub fn random_unit_vector() -> Vector3<f64> {
-        Self::random_in_unit_sphere().normalize()
+        let (u, v) = random::sample_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+
+        Vector3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_in_unit_sphere() -> Vector3<f64> {
-        loop {
-            let p = Vector3::random_float_range(-1.0..1.0);
-            if p.length_squared() < 1.0 {
-                break p;
-            }
+    // Maps a point of the unit square onto the unit disk, keeping its stratification (Shirley and Chiu's
+    // concentric mapping).
+    pub fn sample_unit_disk((u, v): (f64, f64)) -> Vector3<f64> {
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vector3::new(0.0, 0.0, 0.0);
         }
-    }
 
-    pub fn random_in_unit_disk() -> Vector3<f64> {
-        let mut rng = rand::thread_rng();
+        let quarter_pi = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, quarter_pi * (b / a))
+        } else {
+            (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+        };
 
-        loop {
-            let p = Vector3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-            if p.length_squared() < 1.0 {
-                return p;
-            }
-        }
+        Vector3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     pub fn reflect(v: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
@@ -1,88 +1,220 @@
 use core::f64;
 
-use minifb::Window;
+use nalgebra::Vector3;
 use rayon::prelude::*;
 
-use crate::library::check_input as input;
-use crate::library::{color::Color, ray::Ray, scene::Scene, scene::SCENE, interval::Interval, fps_counter::FpsCounter};
+use crate::library::{aov::AovPixel, color::Color, film::{AdaptiveSampling, FilmPixel, SplatTile}, filter::Filter, hit_object::HitObject, random, ray::Ray, sampler::Sampler, scene::Scene, interval::Interval};
+
+// Dimensions of a sample taken by the camera: pixel position, lens position and time.
+const CAMERA_DIMENSIONS: usize = 5;
+// Dimensions taken by every bounce: the free-flight distances in the first two media the incoming ray
+// crosses, the light, the point on it and a medium along the shadow ray for light sampling, then up
+// to three for sampling the material.
+const MEDIUM_DIMENSIONS: usize = 2;
+const LIGHT_DIMENSIONS: usize = 4;
+const BOUNCE_DIMENSIONS: usize = 9;
+
+// Rows rendered together, each band splats its samples into a tile of its own.
+const BAND_HEIGHT: usize = 8;
+
+// Smallest fraction of a pixel the ray differentials of a sample span (pbrt-v4, `CameraBase`).
+const MIN_DIFFERENTIAL_SCALE: f64 = 0.125;
+
+// How the samples of a frame are drawn and accumulated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleSettings {
+    pub sampler: Sampler,
+    // Every sample's numbers are keyed by the seed, its pixel and its index within the pixel, so
+    // the same seed always gives the same image.
+    pub seed: u64,
+    // Skips converged pixels and gives noisy ones extra samples.
+    pub adaptive: Option<AdaptiveSampling>,
+    pub filter: Filter,
+    // Samples per pixel the whole render is expected to take, None for a single frame's worth.
+    pub sample_target: Option<usize>,
+}
 
-use crate::library::constants::{WIDTH, HEIGHT};
+// Adds one frame of samples for every pixel to the film, and to the output variable buffer when one
+// is given. Returns the number of pixels that still need samples.
+pub fn render_frame(scene: &Scene, film: &mut [FilmPixel], aov_buffer: Option<&mut [AovPixel]>, settings: &SampleSettings) -> usize {
+    let width = scene.camera.image_width;
+    let height = scene.camera.image_height;
+    let band_size = width * BAND_HEIGHT;
 
-pub fn render(window: &Window, mut buffer: Vec<Color>) -> (Vec<Color>, bool) {
-    let reset_accumulation = input::check_input(&window, &mut SCENE.write().unwrap());
-    
-    if reset_accumulation {
-        buffer = vec![Color::new(0.0, 0.0, 0.0); WIDTH * HEIGHT];
-    }
+    let aov_bands: Vec<Option<&mut [AovPixel]>> = match aov_buffer {
+        Some(aov_buffer) => aov_buffer.chunks_mut(band_size).map(Some).collect(),
+        None => (0..film.len().div_ceil(band_size)).map(|_| None).collect(),
+    };
 
-    let scene = SCENE.read().unwrap();
-    
     // Parallelize rendering using Rayon
-    buffer
-        .par_chunks_mut(WIDTH)
+    let bands: Vec<(SplatTile, usize)> = film
+        .par_chunks_mut(band_size)
+        .zip(aov_bands.into_par_iter())
         .enumerate()
-        .for_each(|(j, row)| {
-            for i in 0..WIDTH {
-                render_pixel(row, i, j, &scene);
-            }
-        });
-
-    (buffer, reset_accumulation)
+        .map(|(band, (pixels, mut aov_pixels))| {
+            let first_row = band * BAND_HEIGHT;
+            let mut tile = SplatTile::new(first_row..first_row + pixels.len() / width, width, height, &settings.filter);
+
+            let active_pixels = pixels.iter_mut().enumerate()
+                .map(|(index, pixel)| {
+                    let aov_pixel = aov_pixels.as_deref_mut().map(|aov_pixels| &mut aov_pixels[index]);
+                    render_pixel(pixel, aov_pixel, &mut tile, index % width, first_row + index / width, scene, settings) as usize
+                })
+                .sum();
+
+            (tile, active_pixels)
+        })
+        .collect();
+
+    // Tiles overlap where the filter reaches into neighbouring bands, so they are added one at a time.
+    bands.iter()
+        .map(|(tile, active_pixels)| {
+            tile.add_to(film);
+            active_pixels
+        })
+        .sum()
 }
 
-fn render_pixel(row: &mut[Color], i: usize, j: usize, scene: &Scene) {
-    let mut color = Color::new(0.0, 0.0, 0.0);
-    
-    for _ in 0..scene.camera.defaults.samples_per_pixel {
-        let ray = scene.camera.get_ray(i, j);
-        color += ray_color(ray, scene.camera.defaults.max_depth, &scene);
+// Returns whether the pixel still needs samples.
+fn render_pixel(pixel: &mut FilmPixel, mut aov_pixel: Option<&mut AovPixel>, tile: &mut SplatTile, i: usize, j: usize, scene: &Scene, settings: &SampleSettings) -> bool {
+    let samples_per_pixel = scene.camera.defaults.samples_per_pixel;
+    let samples = match settings.adaptive {
+        Some(adaptive) => adaptive.samples_for(pixel, samples_per_pixel),
+        None => samples_per_pixel,
+    };
+    let differential_scale = differential_scale(settings.sample_target.unwrap_or(samples_per_pixel));
+
+    for _ in 0..samples {
+        random::start_sample(settings.sampler, settings.seed, j * scene.camera.image_width + i, pixel.samples());
+
+        let (offset_x, offset_y) = random::sample_2d();
+        let offset = (offset_x - 0.5, offset_y - 0.5);
+
+        let ray = scene.camera.get_ray(i, j, offset, differential_scale);
+        let mut first_hit = aov_pixel.is_some().then(AovPixel::default);
+        let color = ray_color(ray, scene.camera.defaults.max_depth, scene, None, first_hit.as_mut());
+
+        pixel.add_sample(color);
+        tile.splat(i as f64 + offset.0, j as f64 + offset.1, color, &settings.filter);
+
+        if let (Some(aov_pixel), Some(first_hit)) = (aov_pixel.as_deref_mut(), first_hit) {
+            aov_pixel.add_sample(&first_hit);
+        }
     }
 
-    write_color(&mut row[i], color, scene.camera.defaults.samples_per_pixel);
+    settings.adaptive.is_none_or(|adaptive| !adaptive.is_converged(pixel))
 }
 
-fn ray_color(ray: Ray, depth: usize, scene: &Scene) -> Color {
-    if depth <= 0 {
+// The samples of a pixel together already average the texture over it, so every one of them only
+// filters over its share of the pixel. The share follows the samples the render is expected to take,
+// so all samples of a pixel filter over the same footprint.
+fn differential_scale(samples: usize) -> f64 {
+    (1.0 / (samples.max(1) as f64).sqrt()).max(MIN_DIFFERENTIAL_SCALE)
+}
+
+// `scattering_pdf` is the density with which the previous bounce sampled the ray. It is None for
+// camera rays and delta lobes, whose lights cannot also be found by light sampling.
+// `first_hit` receives the output variables of the intersection this ray finds.
+fn ray_color(ray: Ray, depth: usize, scene: &Scene, scattering_pdf: Option<f64>, first_hit: Option<&mut AovPixel>) -> Color {
+    if depth == 0 {
         return Color::new(0.0, 0.0, 0.0);
     }
 
+    let bounce = scene.camera.defaults.max_depth - depth;
+    let dimension = CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS;
+
+    random::set_dimension(dimension);
     let interval = Interval::new(0.001, f64::INFINITY);
     if let Some(hit_object) = scene.hittable_list.hit(ray, interval) {
-        let color_from_emission = hit_object.material.emitted(hit_object.u, hit_object.v, hit_object.point);
-        
-        if let Some((attenuation, scattered)) = hit_object.material.scatter(ray, &hit_object) {
-            let color_from_scatter = attenuation * ray_color(scattered, depth-1, scene);
-            return color_from_emission + color_from_scatter;
+        if let Some(first_hit) = first_hit {
+            *first_hit = AovPixel::from_hit(ray, &hit_object);
+        }
+
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let dimension = dimension + MEDIUM_DIMENSIONS;
+
+        if hit_object.material.is_emissive() {
+            let weight = match scattering_pdf {
+                Some(pdf) => power_heuristic(pdf, light_pdf(scene, ray.origin, ray.direction)),
+                None => 1.0,
+            };
+
+            color += hit_object.material.emitted(&hit_object) * weight;
         }
-        
-        return color_from_emission;
+
+        // Lights themselves do not scatter, so they need no shadow ray either.
+        if !hit_object.material.is_delta() && !hit_object.material.is_emissive() {
+            random::set_dimension(dimension);
+            color += sample_light(scene, ray, &hit_object);
+        }
+
+        random::set_dimension(dimension + LIGHT_DIMENSIONS);
+        if let Some(sample) = hit_object.material.sample(ray, &hit_object) {
+            let pdf = if sample.is_delta { None } else { Some(sample.pdf) };
+            color += sample.attenuation() * ray_color(sample.ray, depth-1, scene, pdf, None);
+        }
+
+        return color;
+    }
+
+    if let Some(first_hit) = first_hit {
+        *first_hit = AovPixel::from_miss(scene);
     }
 
     scene.camera.defaults.background
 }
 
-fn write_color(pixel: &mut Color, mut color: Color, samples_per_pixel: usize) {
-    color *= 1.0 / samples_per_pixel as f64;
+// Density of light sampling generating the direction, averaged over all lights since each is picked
+// with the same probability.
+fn light_pdf(scene: &Scene, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+    if scene.lights.is_empty() {
+        return 0.0;
+    }
+
+    let sum: f64 = scene.lights.iter().map(|light| light.pdf_value(origin, direction)).sum();
+    sum / scene.lights.len() as f64
+}
+
+// Multiple importance sampling weight of a sample drawn with `pdf`, when `other_pdf` could have drawn it as well.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
 
-    *pixel += color;
+    a / (a + b)
 }
 
-pub fn get_clamped_buffer(buffer: &Vec<Color>, fps_counter: &mut FpsCounter, frame_index: &mut usize, reset_accumulation: bool) -> Vec<u32> {
-    if reset_accumulation {
-        *frame_index = 1;
+// Next-event estimation: samples a direction towards a random light and returns the emission
+// found along it, weighted against finding the same light by scattering.
+fn sample_light(scene: &Scene, ray: Ray, hit_object: &HitObject) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if scene.lights.is_empty() {
+        return black;
+    }
+
+    let index = (random::sample_1d() * scene.lights.len() as f64) as usize;
+    let light = &scene.lights[index.min(scene.lights.len() - 1)];
+    let direction = light.random(hit_object.point);
+
+    let pdf = light_pdf(scene, hit_object.point, direction);
+    if pdf <= 0.0 {
+        return black;
     }
-    
-    let mut accumulator: Vec<u32> = vec![0; WIDTH * HEIGHT];
-    accumulator.iter_mut().zip(buffer.iter()).for_each(|(acc_pixel, buffer_pixel)| {
-        let mut color = *buffer_pixel * (1.0 / *frame_index as f64);
-        color = color.linear_to_gamma();
-        color = color.clamp();
-        *acc_pixel = color.to_u32();
-    });
 
-    fps_counter.update(&mut accumulator);
+    // Whatever is hit first is the light seen in this direction, any other object blocks it.
+    let shadow_ray = Ray::new(hit_object.point, direction, ray.time);
+    let Some(light_hit) = scene.hittable_list.hit(shadow_ray, Interval::new(0.001, f64::INFINITY)) else {
+        return black;
+    };
+
+    if !light_hit.material.is_emissive() {
+        return black;
+    }
 
-    *frame_index += 1;
+    let emitted = light_hit.material.emitted(&light_hit);
+    let scattering = hit_object.material.eval(ray, hit_object, direction);
+    let weight = power_heuristic(pdf, hit_object.material.pdf(ray, hit_object, direction));
 
-    accumulator
+    scattering * emitted * (weight / pdf)
 }
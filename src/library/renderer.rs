@@ -0,0 +1,163 @@
+use crate::library::{aov::{Aov, AovPixel}, color::Color, film::{AdaptiveSampling, FilmPixel}, filter::Filter, render::{self, SampleSettings}, random, sampler::Sampler, scene::Scene, tone_mapping::DisplayTransform};
+
+// Linear colors of a rendered image, stored row by row from the top left.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    // Averaged output variables that were enabled on the renderer, in the order they were requested.
+    pub aovs: Vec<(Aov, Vec<Color>)>,
+}
+
+impl Framebuffer {
+    // Tone mapped and sRGB encoded colors, ready to be shown or written to an 8-bit image.
+    pub fn to_display_colors<'a>(&'a self, display: &'a DisplayTransform) -> impl Iterator<Item = Color> + 'a {
+        self.pixels.iter().map(|color| display.apply(*color))
+    }
+
+    // The averaged values of an output variable, None if it was not accumulated.
+    pub fn aov(&self, aov: Aov) -> Option<&[Color]> {
+        self.aovs.iter().find(|(other, _)| *other == aov).map(|(_, pixels)| pixels.as_slice())
+    }
+}
+
+// Progressive renderer, every frame adds one set of samples per pixel to the accumulated image.
+pub struct Renderer {
+    scene: Scene,
+    film: Vec<FilmPixel>,
+    aovs: Vec<Aov>,
+    aov_accumulation: Vec<AovPixel>,
+    settings: SampleSettings,
+    // Pixels that still needed samples after the last frame.
+    active_pixels: usize,
+    frame_count: usize,
+}
+
+impl Renderer {
+    pub fn new(scene: Scene) -> Self {
+        let film = vec![FilmPixel::default(); scene.camera.pixel_count()];
+        let active_pixels = film.len();
+
+        Renderer {
+            scene,
+            film,
+            aovs: Vec::new(),
+            aov_accumulation: Vec::new(),
+            settings: SampleSettings { seed: random::DEFAULT_SEED, ..SampleSettings::default() },
+            active_pixels,
+            frame_count: 0,
+        }
+    }
+
+    // With adaptive sampling, frames only sample the pixels that have not reached the noise threshold yet.
+    pub fn set_adaptive_sampling(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.settings.adaptive = adaptive;
+    }
+
+    // Accumulates the given output variables alongside the image from now on, discarding the frames so far.
+    pub fn set_aovs(&mut self, aovs: &[Aov]) {
+        self.aovs = aovs.to_vec();
+        self.reset();
+    }
+
+    // Renders with the same scene, settings and seed are identical. Takes effect for the samples still to come,
+    // so set it before the first frame.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.settings.seed = seed;
+    }
+
+    // Takes effect for the samples still to come, so set it before the first frame.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.settings.filter = filter;
+    }
+
+    // Takes effect for the samples still to come, so set it before the first frame.
+    pub fn set_sampler(&mut self, sampler: Sampler) {
+        self.settings.sampler = sampler;
+    }
+
+    // Samples per pixel the render is expected to take in total, each sample filters textures over
+    // 1/sqrt of a pixel. Without a target every sample covers a frame's share. Set it before the first frame.
+    pub fn set_sample_target(&mut self, samples_per_pixel: usize) {
+        self.settings.sample_target = Some(samples_per_pixel);
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    // Call `reset` after changing the scene, the frames accumulated so far no longer match it.
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    // Discards the accumulated frames, resizing the image if the camera's resolution changed.
+    pub fn reset(&mut self) {
+        self.film = vec![FilmPixel::default(); self.scene.camera.pixel_count()];
+        self.active_pixels = self.film.len();
+        self.aov_accumulation.clear();
+        if !self.aovs.is_empty() {
+            self.aov_accumulation.resize(self.scene.camera.pixel_count(), AovPixel::default());
+        }
+        self.frame_count = 0;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    // Total number of samples taken since the last reset.
+    pub fn sample_count(&self) -> usize {
+        self.film.iter().map(FilmPixel::samples).sum()
+    }
+
+    // Whether adaptive sampling is enabled and every pixel reached the noise threshold.
+    pub fn is_converged(&self) -> bool {
+        self.settings.adaptive.is_some() && self.active_pixels == 0
+    }
+
+    pub fn render_frame(&mut self) {
+        let aov_buffer = (!self.aovs.is_empty()).then_some(self.aov_accumulation.as_mut_slice());
+        self.active_pixels = render::render_frame(&self.scene, &mut self.film, aov_buffer, &self.settings);
+        self.frame_count += 1;
+    }
+
+    // Accumulates the given number of frames and returns the resulting image. Stops early once
+    // adaptive sampling has converged. The frames set the sample target unless one was set before.
+    pub fn render(&mut self, frames: usize) -> Framebuffer {
+        if self.settings.sample_target.is_none() {
+            self.set_sample_target(frames * self.scene.camera.defaults.samples_per_pixel);
+        }
+
+        for _ in 0..frames {
+            if self.is_converged() {
+                break;
+            }
+            self.render_frame();
+        }
+
+        self.framebuffer()
+    }
+
+    // The image reconstructed from all samples accumulated so far.
+    pub fn framebuffer(&self) -> Framebuffer {
+        Framebuffer {
+            width: self.scene.camera.image_width,
+            height: self.scene.camera.image_height,
+            pixels: self.film.iter().map(FilmPixel::color).collect(),
+            aovs: self.aovs.iter()
+                .map(|aov| {
+                    let pixels = self.aov_accumulation.iter().zip(self.film.iter())
+                        .map(|(pixel, film_pixel)| pixel.average(*aov, film_pixel.samples()))
+                        .collect();
+                    (*aov, pixels)
+                })
+                .collect(),
+        }
+    }
+}
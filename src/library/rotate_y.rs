@@ -14,8 +14,23 @@ pub struct RotateY {
 impl RotateY {
     pub fn new(object: Hittable, angle: f64) -> Self {
         let radians = Camera::degrees_to_radians(angle);
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        Self::from_sin_cos(object, radians.sin(), radians.cos())
+    }
+
+    // The same rotation applied to another object.
+    pub fn with_object(&self, object: Hittable) -> Self {
+        Self::from_sin_cos(object, self.sin_theta, self.cos_theta)
+    }
+
+    pub fn object(&self) -> &Hittable {
+        &self.object
+    }
+
+    pub fn object_mut(&mut self) -> &mut Hittable {
+        &mut self.object
+    }
+
+    fn from_sin_cos(object: Hittable, sin_theta: f64, cos_theta: f64) -> Self {
         let bounding_box = object.get_bounding_box();
 
         let mut min = Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
@@ -47,19 +62,20 @@ impl RotateY {
     }
 }
 
-impl HittableTrait for RotateY {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
-        // Change the ray from world space to object space
-        let mut origin = ray.origin;
-        let mut direction = ray.direction;
-
-        origin.x = self.cos_theta * ray.origin.x - self.sin_theta * ray.origin.z;
-        origin.z = self.sin_theta * ray.origin.x + self.cos_theta * ray.origin.z;
+impl RotateY {
+    fn to_object(&self, v: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * v.x - self.sin_theta * v.z, v.y, self.sin_theta * v.x + self.cos_theta * v.z)
+    }
 
-        direction.x = self.cos_theta * ray.direction.x - self.sin_theta * ray.direction.z;
-        direction.z = self.sin_theta * ray.direction.x + self.cos_theta * ray.direction.z;
+    fn to_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(self.cos_theta * v.x + self.sin_theta * v.z, v.y, -self.sin_theta * v.x + self.cos_theta * v.z)
+    }
+}
 
-        let rotated_ray = Ray::new(origin, direction, ray.time);
+impl HittableTrait for RotateY {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
+        // Change the ray from world space to object space
+        let rotated_ray = ray.transform(|v| self.to_object(v), |v| self.to_object(v));
 
         // Determine where (if any) an intersection occurs in object space
         if let Some(mut hit_object) = self.object.hit(rotated_ray, ray_t) {
@@ -68,13 +84,22 @@ impl HittableTrait for RotateY {
             point.x =  self.cos_theta * hit_object.point.x + self.sin_theta * hit_object.point.z;
             point.z = -self.sin_theta * hit_object.point.x + self.cos_theta * hit_object.point.z;
 
-            // Change the normal from object space to world space
+            // Change the normals from object space to world space
             let mut normal = hit_object.normal;
             normal.x =  self.cos_theta * hit_object.normal.x + self.sin_theta * hit_object.normal.z;
             normal.z = -self.sin_theta * hit_object.normal.x + self.cos_theta * hit_object.normal.z;
 
+            let mut geometric_normal = hit_object.geometric_normal;
+            geometric_normal.x =  self.cos_theta * hit_object.geometric_normal.x + self.sin_theta * hit_object.geometric_normal.z;
+            geometric_normal.z = -self.sin_theta * hit_object.geometric_normal.x + self.cos_theta * hit_object.geometric_normal.z;
+
             hit_object.point = point;
             hit_object.normal = normal;
+            hit_object.geometric_normal = geometric_normal;
+
+            // The texture coordinate differentials do not depend on the orientation, the point offsets do.
+            hit_object.differentials.dpdx = self.to_world(hit_object.differentials.dpdx);
+            hit_object.differentials.dpdy = self.to_world(hit_object.differentials.dpdy);
 
             return Some(hit_object);
         }
@@ -85,4 +110,13 @@ impl HittableTrait for RotateY {
     fn get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
+
+    // Rotations preserve solid angles, so the object's density applies to the rotated direction as is.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        self.object.pdf_value(self.to_object(origin), self.to_object(direction))
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        self.to_world(self.object.random(self.to_object(origin)))
+    }
 }
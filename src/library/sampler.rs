@@ -0,0 +1,295 @@
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+use crate::library::random;
+
+// Samples per block of the stratified sampler, a 4x4 grid for pairs of dimensions.
+const STRATA: u64 = 16;
+const STRATA_GRID: u32 = 4;
+
+// Dimensions the Halton sampler has a prime base for, higher ones fall back to random numbers.
+const HALTON_DIMENSIONS: usize = 1024;
+
+// Sequence the random numbers of a camera sample are drawn from. Every sample of a pixel is a point
+// in a high-dimensional cube: the camera takes the first dimensions, every bounce the next few.
+// Points that fill the cube more evenly than random ones make the image converge faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sampler {
+    // Independent uniform random numbers.
+    Independent,
+    // Jittered strata: every block of 16 consecutive samples of a pixel falls into 16 different
+    // intervals of each dimension, and into the cells of a 4x4 grid for pairs of dimensions.
+    Stratified,
+    // Halton sequence with a prime base per dimension and Owen scrambled digits, scrambled
+    // independently for every pixel and dimension.
+    Halton,
+    // Sobol (0, 2)-sequence with Owen scrambling, shuffled independently for every pair of dimensions
+    // (Burley, "Practical Hash-based Owen Scrambling", 2020).
+    #[default]
+    Sobol,
+}
+
+impl Sampler {
+    pub const ALL: [Sampler; 4] = [Sampler::Independent, Sampler::Stratified, Sampler::Halton, Sampler::Sobol];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sampler::Independent => "independent",
+            Sampler::Stratified => "stratified",
+            Sampler::Halton => "halton",
+            Sampler::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Sampler> {
+        Self::ALL.into_iter().find(|sampler| sampler.name() == name)
+    }
+
+    // Value of one dimension of sample `index`. `key` stands for the render seed and the pixel.
+    pub fn get_1d(self, key: u64, index: u64, dimension: usize) -> f64 {
+        match self {
+            Sampler::Independent => random::rng().gen(),
+            Sampler::Stratified => {
+                let stratum = Self::stratum(key, index, dimension);
+                (stratum as f64 + random::rng().gen::<f64>()) / STRATA as f64
+            },
+            Sampler::Halton => Self::halton(key, index, dimension),
+            Sampler::Sobol => {
+                let seed = random::mix(key ^ random::mix(dimension as u64)) as u32;
+                let index = nested_uniform_scramble(index as u32, seed);
+                to_unit(nested_uniform_scramble(index.reverse_bits(), hash_u32(seed, 1)))
+            },
+        }
+    }
+
+    // Values of the pair of dimensions starting at `dimension`, stratified together.
+    pub fn get_2d(self, key: u64, index: u64, dimension: usize) -> (f64, f64) {
+        match self {
+            Sampler::Independent => (random::rng().gen(), random::rng().gen()),
+            Sampler::Stratified => {
+                let cell = Self::stratum(key, index, dimension) as u32;
+                let (jitter_x, jitter_y): (f64, f64) = (random::rng().gen(), random::rng().gen());
+
+                (
+                    ((cell % STRATA_GRID) as f64 + jitter_x) / STRATA_GRID as f64,
+                    ((cell / STRATA_GRID) as f64 + jitter_y) / STRATA_GRID as f64,
+                )
+            },
+            Sampler::Halton => (Self::halton(key, index, dimension), Self::halton(key, index, dimension + 1)),
+            Sampler::Sobol => {
+                let seed = random::mix(key ^ random::mix(dimension as u64)) as u32;
+                let index = nested_uniform_scramble(index as u32, seed);
+
+                (
+                    to_unit(nested_uniform_scramble(index.reverse_bits(), hash_u32(seed, 1))),
+                    to_unit(nested_uniform_scramble(sobol_second_dimension(index), hash_u32(seed, 2))),
+                )
+            },
+        }
+    }
+
+    // Stratum of the sample within its block, in a random order that differs per block and dimension.
+    fn stratum(key: u64, index: u64, dimension: usize) -> u64 {
+        let block = index / STRATA;
+        let seed = random::mix(key ^ random::mix(dimension as u64 ^ random::mix(block))) as u32;
+        permute((index % STRATA) as u32, STRATA as u32, seed) as u64
+    }
+
+    fn halton(key: u64, index: u64, dimension: usize) -> f64 {
+        let primes = primes();
+        if dimension >= primes.len() {
+            return random::rng().gen();
+        }
+
+        let seed = random::mix(key ^ random::mix(dimension as u64)) as u32;
+        owen_scrambled_radical_inverse(index, primes[dimension], seed)
+    }
+}
+
+fn to_unit(value: u32) -> f64 {
+    value as f64 / 4_294_967_296.0
+}
+
+fn hash_u32(seed: u32, value: u32) -> u32 {
+    random::mix(((seed as u64) << 32) | value as u64) as u32
+}
+
+// Mirrors the digits of `index` in the given base around the decimal point, permuting every digit
+// with a permutation picked by the seed and the digits before it. This keeps the stratification of
+// the sequence but breaks up the correlation between large bases, whose unscrambled points lie on
+// lines for the first `base` samples. The zeros past the last digit of the index are permuted as
+// well, down to the 32 bits of precision the Sobol sampler has too (pbrt-v4,
+// `OwenScrambledRadicalInverse`).
+fn owen_scrambled_radical_inverse(mut index: u64, base: u64, seed: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = 1.0;
+    let mut reversed_digits = 0u64;
+
+    while scale > 1.0 / 4_294_967_296.0 {
+        let digit = index % base;
+        index /= base;
+
+        let digit_seed = random::mix(seed as u64 ^ (reversed_digits << 32)) as u32;
+        reversed_digits = reversed_digits * base + permute(digit as u32, base as u32, digit_seed) as u64;
+        scale *= inverse_base;
+    }
+
+    (reversed_digits as f64 * scale).min(1.0 - f64::EPSILON / 2.0)
+}
+
+fn primes() -> &'static [u64] {
+    static PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u64> = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut candidate = 2;
+
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes.iter().take_while(|prime| *prime * *prime <= candidate).all(|prime| candidate % prime != 0) {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+
+        primes
+    })
+}
+
+// Second dimension of the Sobol sequence, generated by the primitive polynomial x + 1. The first
+// dimension is the bit reversed index.
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+
+    for bit in 0..32 {
+        if index & (1 << bit) != 0 {
+            result ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+// Owen scrambling of the bits of `value`, which keeps the stratification of Sobol points.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut value: u32, seed: u32) -> u32 {
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50_b47c);
+    value ^= value.wrapping_mul(0xb82f_1e52);
+    value ^= value.wrapping_mul(0xc7af_e638);
+    value ^= value.wrapping_mul(0x8d22_f6e6);
+    value
+}
+
+// Element `index` of a random permutation of 0..length picked by `seed` (Kensler, "Correlated
+// Multi-Jittered Sampling", 2013).
+fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < length {
+            break;
+        }
+    }
+
+    (index.wrapping_add(seed)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(value: f64, cells: usize) -> usize {
+        (value * cells as f64) as usize
+    }
+
+    #[test]
+    fn sobol_puts_one_of_16_samples_in_every_cell_of_a_4x4_grid() {
+        for key in 0..8 {
+            for dimension in [0, 2, 5, 41] {
+                let mut cells = [0; 16];
+                for index in 0..16 {
+                    let (x, y) = Sampler::Sobol.get_2d(key, index, dimension);
+                    cells[cell(y, 4) * 4 + cell(x, 4)] += 1;
+                }
+
+                assert_eq!(cells, [1; 16], "key {key}, dimension {dimension}");
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_blocks_cover_every_cell_of_a_4x4_grid() {
+        for key in 0..8 {
+            for block in 0..3 {
+                let mut cells = [0; 16];
+                for index in block * STRATA..(block + 1) * STRATA {
+                    let (x, y) = Sampler::Stratified.get_2d(key, index, 3);
+                    cells[cell(y, 4) * 4 + cell(x, 4)] += 1;
+                }
+
+                assert_eq!(cells, [1; 16], "key {key}, block {block}");
+            }
+        }
+    }
+
+    #[test]
+    fn halton_puts_one_of_base_samples_in_every_interval() {
+        for dimension in [0, 1, 6, 300, HALTON_DIMENSIONS - 1] {
+            let base = primes()[dimension] as usize;
+            let mut intervals = vec![0; base];
+            for index in 0..base as u64 {
+                intervals[cell(Sampler::Halton.get_1d(3, index, dimension), base)] += 1;
+            }
+
+            assert!(intervals.iter().all(|count| *count == 1), "dimension {dimension}");
+        }
+    }
+
+    // Unscrambled, the first samples of two neighbouring large bases lie on a few lines and leave most
+    // cells of a grid empty. Random points fill about 63% of them.
+    #[test]
+    fn halton_pairs_of_large_bases_are_not_correlated() {
+        let occupied = |dimension: usize| {
+            let mut cells = [false; 256];
+            for index in 0..256 {
+                let (x, y) = Sampler::Halton.get_2d(11, index, dimension);
+                cells[cell(y, 16) * 16 + cell(x, 16)] = true;
+            }
+            cells.iter().filter(|cell| **cell).count()
+        };
+
+        for dimension in [200, 300, 600] {
+            assert!(occupied(dimension) > 128, "dimension {dimension}: {} cells", occupied(dimension));
+        }
+    }
+}
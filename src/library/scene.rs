@@ -1,25 +1,32 @@
-use std::sync::RwLock;
+use std::sync::Arc;
 
 use nalgebra::Vector3;
-use once_cell::sync::Lazy;
 
-use crate::library::{camera::{Camera, CameraDefaults}, color::Color, image::Image, material::Material, perlin::Perlin, vector3::Vector3Extensions};
+use crate::library::constants::{DEFAULT_IMAGE_WIDTH, DEFAULT_ASPECT_RATIO};
+use crate::library::{camera::{Camera, CameraDefaults}, color::Color, image::{ColorSpace, ImageCache}, image_texture::ImageTexture, material::Material, perlin::Perlin, random, vector3::Vector3Extensions};
 use crate::library::{hittable_list::HittableList, quad::Quad, rotate_y::RotateY, translate::Translate, sphere::Sphere, quadbox::Quadbox};
 use crate::library::material::Material::*;
-use crate::library::texture::Texture::*;
-use crate::library::hittable::Hittable::*;
+use crate::library::texture::{Texture, Texture::*};
+use crate::library::hittable::{Hittable, Hittable::*};
 
-use super::{constant_medium::ConstantMedium, triangle::Triangle};
+use super::{bvh::BvhNode, constant_medium::ConstantMedium, triangle::Triangle};
 
-pub static SCENE: Lazy<RwLock<Scene>> = Lazy::new(|| RwLock::new(Scene::new(10)));
+pub const DEFAULT_SCENE: usize = 10;
 
 pub struct Scene {
     pub hittable_list: HittableList,
     pub camera: Camera,
+    // Emissive primitives that are sampled directly for next-event estimation.
+    pub lights: Vec<Hittable>,
+    // Every distinct material, in the order the objects first use them. Hits carry their material's index in it.
+    pub materials: Vec<Material>,
 }
 
 impl Scene {
     pub fn new(scene: usize) -> Self {
+        // Scenes with random content come out the same every time.
+        random::reseed(random::DEFAULT_SEED);
+
         match scene {
             1 => Self::random_spheres(),
             2 => Self::two_spheres(),
@@ -35,6 +42,28 @@ impl Scene {
         }
     }
 
+    // Changes the size of the rendered image, keeping the camera's field of view.
+    pub fn set_resolution(&mut self, image_width: usize, aspect_ratio: f64) {
+        self.camera = Camera::init(CameraDefaults { image_width, aspect_ratio, ..self.camera.defaults });
+    }
+
+    // Collects the lights and materials of the scene and builds the bounding volume hierarchy over its objects.
+    pub fn new_from_list(mut hittable_list: HittableList, camera: Camera) -> Self {
+        let mut lights = Vec::new();
+        let mut materials = Vec::new();
+        for object in hittable_list.objects.iter_mut() {
+            object.assign_material_ids(&mut materials);
+            object.collect_lights(&mut lights);
+        }
+
+        Scene {
+            hittable_list: hittable_list.into_bvh(),
+            camera,
+            lights,
+            materials,
+        }
+    }
+
     fn random_spheres() -> Self {
         let mut hittable_list = HittableList::new();
 
@@ -45,6 +74,11 @@ impl Scene {
             1000.0,
             Lambertian(ground_texture),
         )));
+
+        let mut images = ImageCache::new();
+
+        // Every small diffuse sphere shares the one earth material.
+        let earth = Arc::new(Lambertian(Self::image_texture(&mut images, "assets/earth_1024.jpg")));
         
         for a in (-7..7).step_by(4) {
             for b in (-5..5).step_by(2) {
@@ -58,11 +92,9 @@ impl Scene {
                         // diffuse
                         // let albedo = SolidColor(Color::random() * Color::random());
                         // material = Lambertian(albedo);
-                        let texture = Image(Image::load_image("assets/earth_1024.jpg"));
-                        let material = Lambertian(texture);
                         // let center2 = center + Vector3::new(0.0,  Material::random_float_range(0.0..0.5), 0.0);
                         // hittable_list.add(Arc::new(Sphere::new_moving(center, center2, 0.2, material)));
-                        hittable_list.add(Sphere(Sphere::new_stationary(center, 0.2, material)));
+                        hittable_list.add(Sphere(Sphere::new_stationary(center, 0.2, earth.clone())));
                     } else if choose_material < 0.95 {
                         // metal
                         let albedo = Color::random_range(0.5..1.0);
@@ -96,20 +128,19 @@ impl Scene {
             Metal(Color::new(0.4, 0.4, 0.4), 0.025),
         )));
 
-        let earth_texture = Image(Image::load_image("assets/earth_1024.jpg"));
+        let earth_texture = Self::image_texture(&mut images, "assets/earth_1024.jpg");
         hittable_list.add(Sphere(Sphere::new_stationary(
             Vector3::new(-6.0, 1.0, 0.0),
             1.0,
             Lambertian(earth_texture),
         )));
 
-        // let list = HittableList::new_from_list(Arc::new(BvhNode::new_from_list(hittable_list)));
-        let list = hittable_list;
-
-        Scene {
-            hittable_list: list,
-            camera: Camera::init(
+        Scene::new_from_list(
+            hittable_list,
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.7, 0.8, 1.0),
@@ -121,7 +152,7 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn two_spheres() -> Self {
@@ -141,10 +172,12 @@ impl Scene {
             Lambertian(checker.clone())
         )));
     
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.7, 0.8, 1.0),
@@ -156,21 +189,24 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn earth() -> Self {
         let mut hittable_list = HittableList::new();
     
-        let texture = Image(Image::load_image("assets/earth_1024.jpg"));
+        let mut images = ImageCache::new();
+        let texture = Self::image_texture(&mut images, "assets/earth_1024.jpg");
         let surface = Lambertian(texture);
     
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(0.0, 0.0, 0.0), 2.0, surface)));
     
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.7, 0.8, 1.0),
@@ -182,7 +218,7 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn two_perlin_spheres() -> Self {
@@ -202,10 +238,12 @@ impl Scene {
             Lambertian(perlin_texture.clone())
         )));
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.7, 0.8, 1.0),
@@ -217,7 +255,7 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn quads() -> Self {
@@ -237,10 +275,12 @@ impl Scene {
         hittable_list.add(Quad(Quad::new(Vector3::new(-2.0,  3.0, 1.0), Vector3::new(4.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 4.0), upper_orange)));
         hittable_list.add(Quad(Quad::new(Vector3::new(-2.0, -3.0, 5.0), Vector3::new(4.0, 0.0, 0.0), Vector3::new(0.0, 0.0,-4.0), lower_teal)));
         
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.7, 0.8, 1.0),
@@ -252,7 +292,7 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn simple_light() -> Self {
@@ -262,14 +302,16 @@ impl Scene {
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian(pertext.clone()))));
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(0.0, 2.0, 0.0), 2.0, Lambertian(pertext.clone()))));
     
-        let difflight = DiffuseLight(SolidColor(Color::new(20.0, 20.0, 20.0)));
+        let difflight = Arc::new(DiffuseLight(SolidColor(Color::new(20.0, 20.0, 20.0))));
         hittable_list.add(Quad(Quad::new(Vector3::new(3.0, 1.0, -2.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0), difflight.clone())));
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(0.0, 7.0, 0.0), 2.0, difflight.clone())));
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.0, 0.0, 0.0),
@@ -281,16 +323,16 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     fn cornell_box() -> Self {
         let mut hittable_list = HittableList::new();
     
-        let red   = Lambertian(SolidColor(Color::new(0.65, 0.05, 0.05)));
-        let white = Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73)));
-        let green = Lambertian(SolidColor(Color::new(0.12, 0.45, 0.15)));
-        let light = DiffuseLight(SolidColor(Color::new(15.0, 15.0, 15.0)));
+        let red   = Arc::new(Lambertian(SolidColor(Color::new(0.65, 0.05, 0.05))));
+        let white = Arc::new(Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73))));
+        let green = Arc::new(Lambertian(SolidColor(Color::new(0.12, 0.45, 0.15))));
+        let light = Arc::new(DiffuseLight(SolidColor(Color::new(15.0, 15.0, 15.0))));
     
         hittable_list.add(Quad(Quad::new(Vector3::new(555.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), green.clone())));
         hittable_list.add(Quad(Quad::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), red.clone())));
@@ -317,10 +359,12 @@ impl Scene {
             hittable_list.add(side)
         }
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.0, 0.0, 0.0),
@@ -332,16 +376,16 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
     
     fn cornell_smoke() -> Self {
         let mut hittable_list = HittableList::new();
     
-        let red   = Lambertian(SolidColor(Color::new(0.65, 0.05, 0.05)));
-        let white = Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73)));
-        let green = Lambertian(SolidColor(Color::new(0.12, 0.45, 0.15)));
-        let light = DiffuseLight(SolidColor(Color::new(7.0, 7.0, 7.0)));
+        let red   = Arc::new(Lambertian(SolidColor(Color::new(0.65, 0.05, 0.05))));
+        let white = Arc::new(Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73))));
+        let green = Arc::new(Lambertian(SolidColor(Color::new(0.12, 0.45, 0.15))));
+        let light = Arc::new(DiffuseLight(SolidColor(Color::new(7.0, 7.0, 7.0))));
     
         hittable_list.add(Quad(Quad::new(Vector3::new(555.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), green.clone())));
         hittable_list.add(Quad(Quad::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), red.clone())));
@@ -374,10 +418,12 @@ impl Scene {
         hittable_list.add(ConstantMedium(ConstantMedium::new_from_color(box1, 0.01, Color::new(0.0, 0.0, 0.0))));
         hittable_list.add(ConstantMedium(ConstantMedium::new_from_color(box2, 0.005, Color::new(1.0, 1.0, 1.0))));
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     background: Color::new(0.0, 0.0, 0.0),
@@ -389,12 +435,12 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     pub fn final_scene() -> Self {
         let mut boxes1 = HittableList::new();
-        let ground = Lambertian(SolidColor(Color::new(0.48, 0.83, 0.53)));
+        let ground = Arc::new(Lambertian(SolidColor(Color::new(0.48, 0.83, 0.53))));
 
         let boxes_per_side = 20;
         for i in 0..boxes_per_side {
@@ -434,28 +480,29 @@ impl Scene {
         let boundary = Sphere(Sphere::new_stationary(Vector3::new(0.0, 0.0, 0.0), 5000.0, Dielectric(1.5)));
         hittable_list.add(ConstantMedium(ConstantMedium::new_from_color(boundary, 0.0001, Color::new(1.0, 1.0, 1.0))));
 
-        let emat = Lambertian(Image(Image::load_image("assets/earth_400.jpg")));
+        let mut images = ImageCache::new();
+        let emat = Lambertian(Self::image_texture(&mut images, "assets/earth_400.jpg"));
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(400.0, 200.0, 400.0), 100.0, emat)));
         let pertext = Perlin(Perlin::new(), 0.1);
         hittable_list.add(Sphere(Sphere::new_stationary(Vector3::new(220.0, 280.0, 300.0), 80.0, Lambertian(pertext))));
 
         let mut boxes2 = HittableList::new();
-        let white = Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73)));
+        let white = Arc::new(Lambertian(SolidColor(Color::new(0.73, 0.73, 0.73))));
         let ns = 1000;
         for _ in 0..ns {
             boxes2.add(Sphere(Sphere::new_stationary(Vector3::random_float_range(0.0..165.0), 10.0, white.clone())));
         }
 
-        for object in boxes2.objects {
-            hittable_list.add(Translate(Box::new(Translate::new(
-                RotateY(Box::new(RotateY::new(object, 15.0))), Vector3::new(-100.0, 270.0, 395.0)
-            ))));
-        }
+        hittable_list.add(Translate(Box::new(Translate::new(
+            RotateY(Box::new(RotateY::new(Bvh(Box::new(BvhNode::new_from_list(boxes2))), 15.0))), Vector3::new(-100.0, 270.0, 395.0)
+        ))));
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 10,
                     background: Color::new(0.0, 0.0, 0.0),
@@ -467,14 +514,14 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
     }
 
     pub fn triangle() -> Self {
         let mut hittable_list = HittableList::new();
     
         // Materials
-        let white = Lambertian(SolidColor(Color::new(1.0, 1.0, 1.0)));
+        let white = Arc::new(Lambertian(SolidColor(Color::new(1.0, 1.0, 1.0))));
         let teal =  Lambertian(SolidColor(Color::new(0.0, 0.9, 1.0)));
 
         // Box
@@ -501,10 +548,12 @@ impl Scene {
         hittable_list.add(fog);
 
 
-        Scene {
+        Scene::new_from_list(
             hittable_list,
-            camera: Camera::init(
+            Camera::init(
                 CameraDefaults {
+                    image_width: DEFAULT_IMAGE_WIDTH,
+                    aspect_ratio: DEFAULT_ASPECT_RATIO,
                     samples_per_pixel: 1,
                     max_depth: 50,
                     // background: Color::new(0.7, 0.8, 1.0),
@@ -519,6 +568,48 @@ impl Scene {
                     focus_distance: 10.0,
                 }
             ),
-        }
+        )
+    }
+
+    // Texture of an image shipped with the built-in scenes. Every scene loads its images through one
+    // cache, so an image used by several textures is decoded once.
+    fn image_texture(images: &mut ImageCache, file_path: &str) -> Texture {
+        Image(ImageTexture::new(images.load(file_path, ColorSpace::Srgb).expect("Failed to open image")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{interval::Interval, ray::Ray};
+
+    fn material_id_at(scene: &Scene, origin: Vector3<f64>, direction: Vector3<f64>) -> usize {
+        let hit_object = scene.hittable_list.hit(Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)).unwrap();
+        hit_object.material_id
+    }
+
+    #[test]
+    fn equal_materials_share_an_id() {
+        // Both spheres get their own copy of the checker material.
+        let scene = Scene::new(2);
+        assert_eq!(scene.materials.len(), 1);
+
+        let toward = Vector3::new(0.0, 0.0, -1.0);
+        assert_eq!(material_id_at(&scene, Vector3::new(0.0, -5.0, 20.0), toward), 0);
+        assert_eq!(material_id_at(&scene, Vector3::new(0.0, 5.0, 20.0), toward), 0);
+    }
+
+    #[test]
+    fn hits_carry_the_index_of_their_material() {
+        let scene = Scene::new(7);
+        assert_eq!(scene.materials.len(), 4);
+
+        // Above both boxes, towards the green and the red wall.
+        let origin = Vector3::new(278.0, 400.0, 100.0);
+        let green = material_id_at(&scene, origin, Vector3::new(1.0, 0.0, 0.0));
+        let red = material_id_at(&scene, origin, Vector3::new(-1.0, 0.0, 0.0));
+
+        assert_eq!(scene.materials[green], Lambertian(SolidColor(Color::new(0.12, 0.45, 0.15))));
+        assert_eq!(scene.materials[red], Lambertian(SolidColor(Color::new(0.65, 0.05, 0.05))));
     }
 }
@@ -0,0 +1,382 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::library::constants::{DEFAULT_IMAGE_WIDTH, DEFAULT_ASPECT_RATIO};
+use crate::library::{camera::{Camera, CameraDefaults}, color::Color, image::{ColorSpace, ImageCache}, image_texture::{ImageTexture, TextureFilter, UvTransform, WrapMode}, material::Material, perlin::Perlin, random, texture::Texture};
+use crate::library::{bvh::BvhNode, constant_medium::ConstantMedium, hittable::Hittable, hittable_list::HittableList, obj, scene::Scene};
+use crate::library::{quad::Quad, quadbox::Quadbox, rotate_y::RotateY, sphere::Sphere, translate::Translate, triangle::Triangle};
+
+// Error raised while loading a scene file, points at the offending location when it is known.
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: PathBuf,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}", self.path.display(), line, column, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: Spanned<CameraDescription>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDescription>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDescription>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDescription>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    #[serde(default = "default_image_width")]
+    image_width: usize,
+    #[serde(default = "default_aspect_ratio")]
+    aspect_ratio: f64,
+    #[serde(default = "default_samples_per_pixel")]
+    samples_per_pixel: usize,
+    #[serde(default = "default_max_depth")]
+    max_depth: usize,
+    #[serde(default = "default_background")]
+    background: [f64; 3],
+    #[serde(default = "default_vertical_fov")]
+    vertical_fov: f64,
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    #[serde(default)]
+    defocus_angle: f64,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f64,
+}
+
+fn default_image_width() -> usize { DEFAULT_IMAGE_WIDTH }
+fn default_aspect_ratio() -> f64 { DEFAULT_ASPECT_RATIO }
+fn default_samples_per_pixel() -> usize { 1 }
+fn default_max_depth() -> usize { 50 }
+fn default_background() -> [f64; 3] { [0.7, 0.8, 1.0] }
+fn default_vertical_fov() -> f64 { 40.0 }
+fn default_vup() -> [f64; 3] { [0.0, 1.0, 0.0] }
+fn default_focus_distance() -> f64 { 10.0 }
+fn default_uv_scale() -> [f64; 2] { [1.0, 1.0] }
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    SolidColor { color: [f64; 3] },
+    Checkered { scale: f64, even: [f64; 3], odd: [f64; 3] },
+    Image {
+        path: String,
+        #[serde(default)]
+        color_space: ColorSpaceDescription,
+        #[serde(default)]
+        filter: TextureFilterDescription,
+        #[serde(default)]
+        wrap: WrapModeDescription,
+        #[serde(default = "default_uv_scale")]
+        scale: [f64; 2],
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default)]
+        offset: [f64; 2],
+    },
+    Perlin { scale: f64 },
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ColorSpaceDescription {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TextureFilterDescription {
+    Nearest,
+    #[default]
+    Bilinear,
+    Bicubic,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WrapModeDescription {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+// A texture is either given inline as a color or by the name of an entry in `[textures]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextureReference {
+    Color([f64; 3]),
+    Named(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian { albedo: TextureReference },
+    Metal { albedo: [f64; 3], #[serde(default)] fuzz: f64 },
+    Dielectric { refraction_index: f64 },
+    DiffuseLight { emit: TextureReference },
+    #[serde(alias = "isotropic")]
+    IsoTropic { albedo: TextureReference },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere { center: [f64; 3], center2: Option<[f64; 3]>, radius: f64, material: String },
+    Quad { q: [f64; 3], u: [f64; 3], v: [f64; 3], material: String },
+    Triangle { a: [f64; 3], b: [f64; 3], c: [f64; 3], normals: Option<[[f64; 3]; 3]>, uvs: Option<[[f64; 2]; 3]>, material: String },
+    #[serde(alias = "quadbox")]
+    Box { a: [f64; 3], b: [f64; 3], material: String },
+    Translate { offset: [f64; 3], object: Box<ObjectDescription> },
+    RotateY { angle: f64, object: Box<ObjectDescription> },
+    ConstantMedium { density: f64, albedo: TextureReference, boundary: Box<ObjectDescription> },
+    Group { objects: Vec<ObjectDescription> },
+    Mesh { path: String, material: Option<String> },
+}
+
+// Reads a TOML scene description and builds the scene it describes.
+// Relative file paths inside the description are resolved against the directory of the scene file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    // Noise textures come out the same every time the file is loaded.
+    random::reseed(random::DEFAULT_SEED);
+
+    let source = std::fs::read_to_string(path).map_err(|error| SceneFileError {
+        path: path.to_path_buf(),
+        location: None,
+        message: error.to_string(),
+    })?;
+
+    let loader = Loader {
+        path,
+        directory: path.parent().unwrap_or(Path::new("")),
+        source: &source,
+        images: RefCell::new(ImageCache::new()),
+    };
+    loader.load()
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    directory: &'a Path,
+    source: &'a str,
+    // Shared by the image textures and the textures of meshes, so every file is loaded once per scene.
+    images: RefCell<ImageCache>,
+}
+
+impl Loader<'_> {
+    fn load(&self) -> Result<Scene, SceneFileError> {
+        let description: SceneDescription = toml::from_str(self.source)
+            .map_err(|error| self.error(error.span().map(|span| span.start), error.message().to_string()))?;
+
+        // Noise textures draw from the reseeded generator, so they are built in file order rather than in
+        // the map's order, which changes from run to run.
+        let mut texture_descriptions: Vec<_> = description.textures.iter().collect();
+        texture_descriptions.sort_by_key(|(_, texture)| texture.span().start);
+
+        let mut textures = HashMap::new();
+        for (name, texture) in texture_descriptions {
+            textures.insert(name.as_str(), self.build_texture(texture)?);
+        }
+
+        let mut materials = HashMap::new();
+        for (name, material) in &description.materials {
+            let built = self.build_material(material.get_ref(), &textures)
+                .map_err(|message| self.error(Some(material.span().start), message))?;
+            materials.insert(name.as_str(), Arc::new(built));
+        }
+
+        let mut hittable_list = HittableList::new();
+        for object in &description.objects {
+            let built = self.build_object(object.get_ref(), &textures, &materials)
+                .map_err(|message| self.error(Some(object.span().start), message))?;
+            hittable_list.add(built);
+        }
+
+        let camera = Self::build_camera(description.camera.get_ref())
+            .map_err(|message| self.error(Some(description.camera.span().start), message))?;
+
+        Ok(Scene::new_from_list(hittable_list, Camera::init(camera)))
+    }
+
+    fn build_camera(camera: &CameraDescription) -> Result<CameraDefaults, String> {
+        if camera.image_width == 0 {
+            return Err(String::from("image_width must be at least 1"));
+        }
+
+        if !(camera.aspect_ratio > 0.0 && camera.aspect_ratio.is_finite()) {
+            return Err(String::from("aspect_ratio must be a positive number"));
+        }
+
+        Ok(CameraDefaults {
+            image_width: camera.image_width,
+            aspect_ratio: camera.aspect_ratio,
+            samples_per_pixel: camera.samples_per_pixel,
+            max_depth: camera.max_depth,
+            background: to_color(camera.background),
+            vertical_fov: camera.vertical_fov,
+            lookfrom: to_vector(camera.lookfrom),
+            lookat: to_vector(camera.lookat),
+            vup: to_vector(camera.vup),
+            defocus_angle: camera.defocus_angle,
+            focus_distance: camera.focus_distance,
+        })
+    }
+
+    fn build_texture(&self, texture: &Spanned<TextureDescription>) -> Result<Texture, SceneFileError> {
+        Ok(match texture.get_ref() {
+            TextureDescription::SolidColor { color } => Texture::SolidColor(to_color(*color)),
+            TextureDescription::Checkered { scale, even, odd } => Texture::Checkered(*scale, to_color(*even), to_color(*odd)),
+            TextureDescription::Image { path, color_space, filter, wrap, scale, rotation, offset } => {
+                let color_space = match color_space {
+                    ColorSpaceDescription::Srgb => ColorSpace::Srgb,
+                    ColorSpaceDescription::Linear => ColorSpace::Linear,
+                };
+                let image_path = self.directory.join(path);
+                let image = self.images.borrow_mut().load(&image_path, color_space).map_err(|error| {
+                    self.error(Some(texture.span().start), format!("failed to load image '{}': {}", image_path.display(), error))
+                })?;
+
+                let filter = match filter {
+                    TextureFilterDescription::Nearest => TextureFilter::Nearest,
+                    TextureFilterDescription::Bilinear => TextureFilter::Bilinear,
+                    TextureFilterDescription::Bicubic => TextureFilter::Bicubic,
+                };
+                let wrap = match wrap {
+                    WrapModeDescription::Repeat => WrapMode::Repeat,
+                    WrapModeDescription::Clamp => WrapMode::Clamp,
+                    WrapModeDescription::Mirror => WrapMode::Mirror,
+                };
+                let transform = UvTransform { scale: (scale[0], scale[1]), rotation: *rotation, offset: (offset[0], offset[1]) };
+
+                Texture::Image(ImageTexture { filter, wrap, transform, ..ImageTexture::new(image) })
+            },
+            TextureDescription::Perlin { scale } => Texture::Perlin(Perlin::new(), *scale),
+        })
+    }
+
+    fn build_material(&self, material: &MaterialDescription, textures: &HashMap<&str, Texture>) -> Result<Material, String> {
+        Ok(match material {
+            MaterialDescription::Lambertian { albedo } => Material::Lambertian(resolve_texture(albedo, textures)?),
+            MaterialDescription::Metal { albedo, fuzz } => Material::Metal(to_color(*albedo), fuzz.clamp(0.0, 1.0)),
+            MaterialDescription::Dielectric { refraction_index } => Material::Dielectric(*refraction_index),
+            MaterialDescription::DiffuseLight { emit } => Material::DiffuseLight(resolve_texture(emit, textures)?),
+            MaterialDescription::IsoTropic { albedo } => Material::IsoTropic(resolve_texture(albedo, textures)?),
+        })
+    }
+
+    fn build_object(&self, object: &ObjectDescription, textures: &HashMap<&str, Texture>, materials: &HashMap<&str, Arc<Material>>) -> Result<Hittable, String> {
+        let material = |name: &String| materials.get(name.as_str()).cloned().ok_or(format!("unknown material '{}'", name));
+
+        Ok(match object {
+            ObjectDescription::Sphere { center, center2: None, radius, material: name } => {
+                Hittable::Sphere(Sphere::new_stationary(to_vector(*center), *radius, material(name)?))
+            },
+            ObjectDescription::Sphere { center, center2: Some(center2), radius, material: name } => {
+                Hittable::Sphere(Sphere::new_moving(to_vector(*center), to_vector(*center2), *radius, material(name)?))
+            },
+            ObjectDescription::Quad { q, u, v, material: name } => {
+                Hittable::Quad(Quad::new(to_vector(*q), to_vector(*u), to_vector(*v), material(name)?))
+            },
+            ObjectDescription::Triangle { a, b, c, normals, uvs, material: name } => {
+                let normals = normals.map(|normals| normals.map(to_vector));
+                let uvs = uvs.map(|uvs| uvs.map(|uv| (uv[0], uv[1])));
+                Hittable::Triangle(Triangle::new_with_attributes(to_vector(*a), to_vector(*b), to_vector(*c), normals, uvs, material(name)?))
+            },
+            ObjectDescription::Box { a, b, material: name } => {
+                Hittable::QuadBox(Quadbox::new(to_vector(*a), to_vector(*b), material(name)?))
+            },
+            ObjectDescription::Translate { offset, object } => {
+                let object = self.build_object(object, textures, materials)?;
+                Hittable::Translate(Box::new(Translate::new(object, to_vector(*offset))))
+            },
+            ObjectDescription::RotateY { angle, object } => {
+                let object = self.build_object(object, textures, materials)?;
+                Hittable::RotateY(Box::new(RotateY::new(object, *angle)))
+            },
+            ObjectDescription::ConstantMedium { density, albedo, boundary } => {
+                let boundary = self.build_object(boundary, textures, materials)?;
+                Hittable::ConstantMedium(ConstantMedium::new(boundary, *density, resolve_texture(albedo, textures)?))
+            },
+            ObjectDescription::Group { objects } => {
+                if objects.is_empty() {
+                    return Err(String::from("a group needs at least one object"));
+                }
+
+                let objects = objects.iter()
+                    .map(|object| self.build_object(object, textures, materials))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Hittable::Bvh(Box::new(BvhNode::new(objects)))
+            },
+            ObjectDescription::Mesh { path, material: name } => {
+                let material = name.as_ref().map(material).transpose()?;
+                let mut meshes = obj::load_obj(self.directory.join(path), material, &mut self.images.borrow_mut()).map_err(|error| error.to_string())?;
+
+                if meshes.len() == 1 {
+                    Hittable::TriangleMesh(meshes.pop().unwrap())
+                } else {
+                    Hittable::Bvh(Box::new(BvhNode::new(meshes.into_iter().map(Hittable::TriangleMesh).collect())))
+                }
+            },
+        })
+    }
+
+    fn error(&self, offset: Option<usize>, message: String) -> SceneFileError {
+        SceneFileError {
+            path: self.path.to_path_buf(),
+            location: offset.map(|offset| line_and_column(self.source, offset)),
+            message,
+        }
+    }
+}
+
+fn resolve_texture(reference: &TextureReference, textures: &HashMap<&str, Texture>) -> Result<Texture, String> {
+    match reference {
+        TextureReference::Color(color) => Ok(Texture::SolidColor(to_color(*color))),
+        TextureReference::Named(name) => textures.get(name.as_str()).cloned().ok_or(format!("unknown texture '{}'", name)),
+    }
+}
+
+// Converts a byte offset into the 1-based line and column it points at.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn to_vector(v: [f64; 3]) -> Vector3<f64> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn to_color(c: [f64; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
@@ -1,36 +1,39 @@
 use std::f64::consts::PI;
+use std::sync::Arc;
 
 use nalgebra::Vector3;
 
 use crate::library::basic_lib::*;
-use crate::library::{hittable::HittableTrait, material::Material, vector3::Vector3Extensions};
+use crate::library::{hittable::HittableTrait, material::Material, onb::Onb, random, vector3::Vector3Extensions};
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     center1: Vector3<f64>,
     radius: f64,
-    material: Material,
+    material: Arc<Material>,
+    material_id: usize,
     is_moving: bool,
     center_vec: Vector3<f64>,
     bounding_box: Aabb,
 }
 
 impl Sphere {
-    pub fn new_stationary(center: Vector3<f64>, radius: f64, material: Material) -> Self {
+    pub fn new_stationary(center: Vector3<f64>, radius: f64, material: impl Into<Arc<Material>>) -> Self {
         let rvec = Vector3::new(radius, radius, radius);
         let bounding_box = Aabb::new_from_point(center - rvec, center + rvec);
 
         Sphere {
             center1: center,
             radius,
-            material,
+            material: material.into(),
+            material_id: 0,
             is_moving: false,
             center_vec: center,
             bounding_box,
         }
     }
     
-    pub fn new_moving(center1: Vector3<f64>, center2: Vector3<f64>, radius: f64, material: Material) -> Self {
+    pub fn new_moving(center1: Vector3<f64>, center2: Vector3<f64>, radius: f64, material: impl Into<Arc<Material>>) -> Self {
         let rvec = Vector3::new(radius, radius, radius);
         let box1 = Aabb::new_from_point(center1 - rvec, center1 + rvec);
         let box2 = Aabb::new_from_point(center2 - rvec, center2 + rvec);
@@ -39,7 +42,8 @@ impl Sphere {
         Sphere {
             center1,
             radius,
-            material,
+            material: material.into(),
+            material_id: 0,
             is_moving: true,
             center_vec: center2 - center1,
             bounding_box,
@@ -54,6 +58,26 @@ impl Sphere {
         self.center1 + time * self.center_vec
     }
 
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // Index of the material in the scene's material table, handed to every hit.
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
+
+    // Cosine of the half angle of the cone the sphere covers as seen from the origin,
+    // None if the origin lies inside the sphere.
+    fn cos_theta_max(&self, origin: Vector3<f64>) -> Option<f64> {
+        let distance_squared = (self.center1 - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return None;
+        }
+
+        Some((1.0 - self.radius * self.radius / distance_squared).sqrt())
+    }
+
     fn get_sphere_uv(&self, p: Vector3<f64>) -> (f64, f64) {
         // p: a given point on the sphere of radius one, centered at the origin.
         // u: returned value [0,1] of angle around the Y axis from X=-1.
@@ -69,10 +93,20 @@ impl Sphere {
         let v = theta / PI;
         (u, v)
     }
+
+    // Partial derivatives of the hit point with respect to u and v, for the point p of the unit sphere
+    // as in `get_sphere_uv`. They degenerate at the poles.
+    fn position_derivatives(&self, p: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        let sin_theta = (p.x * p.x + p.z * p.z).sqrt().max(1e-8);
+
+        let dpdu = 2.0 * PI * self.radius * Vector3::new(p.z, 0.0, -p.x);
+        let dpdv = PI * self.radius * Vector3::new(-p.x * p.y / sin_theta, sin_theta, -p.y * p.z / sin_theta);
+        (dpdu, dpdv)
+    }
 }
 
 impl HittableTrait for Sphere {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         let mut center = self.center1;
         if self.is_moving {
             center = self.get_sphere_center(ray.time);
@@ -102,11 +136,39 @@ impl HittableTrait for Sphere {
         let outward_normal = self.calculate_normal(hit_point);
 
         let (u, v) = self.get_sphere_uv(outward_normal);
+        let (dpdu, dpdv) = self.position_derivatives(outward_normal);
 
-        Some(HitObject::new(hit_point, ray, outward_normal, self.material.clone(), t, u, v))
+        Some(HitObject::new(hit_point, ray, outward_normal, &self.material, t, u, v).with_differentials(&ray, dpdu, dpdv).with_material_id(self.material_id))
     }
 
     fn get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
+
+    // Lights are sampled uniformly over the cone they subtend, moving spheres at their starting position.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return 0.0;
+        };
+
+        if self.hit(Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)).is_none() {
+            return 0.0;
+        }
+
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return self.center1 - origin;
+        };
+
+        let (r1, r2) = random::sample_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        Onb::new(self.center1 - origin).transform(Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
 }
\ No newline at end of file
@@ -1,18 +1,19 @@
 use nalgebra::Vector3;
 
-use crate::library::{color::Color, image::Image, perlin::Perlin};
+use crate::library::{color::Color, hit_object::SurfaceDifferentials, image_texture::ImageTexture, perlin::Perlin};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Texture {
     SolidColor(Color),
     Checkered(f64, Color, Color),
-    Image(Image),
+    Image(ImageTexture),
     Perlin(Perlin, f64)
 }
 
 impl Texture {
-    pub fn value(&self, u: f64, v: f64, p: Vector3<f64>) -> Color {
+    // Color at the texture coordinates (u, v) and point p, averaged over the area the differentials span.
+    pub fn value(&self, u: f64, v: f64, p: Vector3<f64>, differentials: &SurfaceDifferentials) -> Color {
         match self {
             Texture::SolidColor(color) => {
                 *color
@@ -20,27 +21,16 @@ impl Texture {
             Texture::Checkered(scale, even, odd) => {
                 let inv_scale = 1.0 / scale;
 
-                let x_integer = (inv_scale * p.x).floor();
-                let y_integer = (inv_scale * p.y).floor();
-                let z_integer = (inv_scale * p.z).floor();
+                // Box filter over the pixel footprint: the cells alternate the sign of (-1)^(x + y + z),
+                // whose average over a box is the product of the averages along each axis.
+                let half_width = (differentials.dpdx.abs() + differentials.dpdy.abs()) * (0.5 * inv_scale);
+                let p = inv_scale * p;
+                let sign = (0..3).map(|axis| Self::average_alternating_sign(p[axis], half_width[axis])).product::<f64>();
 
-                let is_even = (x_integer + y_integer + z_integer) % 2.0 == 0.0;
-
-                if is_even {
-                    *even
-                } else {
-                    *odd
-                }
+                *even * (0.5 * (1.0 + sign)) + *odd * (0.5 * (1.0 - sign))
             }
-            Texture::Image(image) => {
-                let u = u.clamp(0.0, 1.0);
-                let v = 1.0 - v.clamp(0.0, 1.0);  // Flip V to image coordinates
-
-                let i = u * image.width() as f64;
-                let j = v * image.height() as f64;
-                let pixel = image.pixel_data(i as u32, j as u32);
-
-                pixel
+            Texture::Image(image_texture) => {
+                image_texture.value(u, v, differentials)
             }
             Texture::Perlin(perlin_noise, scale) => {
                 let s = *scale * p;
@@ -50,4 +40,19 @@ impl Texture {
             },
         }
     }
-}
\ No newline at end of file
+
+    // Average of (-1)^floor(x) over [x - half_width, x + half_width].
+    fn average_alternating_sign(x: f64, half_width: f64) -> f64 {
+        if half_width < 1e-8 {
+            return if x.floor().rem_euclid(2.0) == 0.0 { 1.0 } else { -1.0 };
+        }
+
+        // The integral from 0 to x, a triangle wave between 0 and 1.
+        let integral = |x: f64| {
+            let (cell, fraction) = (x.floor(), x - x.floor());
+            if cell.rem_euclid(2.0) == 0.0 { fraction } else { 1.0 - fraction }
+        };
+
+        (integral(x + half_width) - integral(x - half_width)) / (2.0 * half_width)
+    }
+}
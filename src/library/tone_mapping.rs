@@ -0,0 +1,217 @@
+use crate::library::color::{linear_to_srgb, Color};
+
+// Operator that compresses scene brightness into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapping {
+    // Clips everything above white.
+    #[default]
+    None,
+    // L / (1 + L) on the luminance, never quite reaching white.
+    Reinhard,
+    // Reinhard that maps the white point to white.
+    ExtendedReinhard,
+    // Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    // Troy Sobotka's AgX, with the polynomial fit by Benjamin Wrensch. Desaturates bright colors
+    // towards white the way film does.
+    Agx,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 5] = [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::ExtendedReinhard, ToneMapping::Aces, ToneMapping::Agx];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapping::None => "none",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::ExtendedReinhard => "extended-reinhard",
+            ToneMapping::Aces => "aces",
+            ToneMapping::Agx => "agx",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ToneMapping> {
+        Self::ALL.into_iter().find(|tone_mapping| tone_mapping.name() == name)
+    }
+
+    // The operator after this one, wrapping around.
+    pub fn next(self) -> ToneMapping {
+        let index = Self::ALL.iter().position(|tone_mapping| *tone_mapping == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+// Turns linear scene colors into display colors: scales them by the exposure, applies the tone
+// mapping operator and encodes the result with the sRGB transfer function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    // Exposure in stops, every stop doubles the brightness.
+    pub exposure: f64,
+    pub tone_mapping: ToneMapping,
+    // Luminance that the extended Reinhard operator maps to white.
+    pub white_point: f64,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform { exposure: 0.0, tone_mapping: ToneMapping::default(), white_point: 4.0 }
+    }
+}
+
+impl DisplayTransform {
+    // sRGB encoded color in [0, 1), ready to be shown or written to an 8-bit image.
+    pub fn apply(&self, color: Color) -> Color {
+        let color = color * 2f64.powf(self.exposure);
+        let color = Color::new(color.r.max(0.0), color.g.max(0.0), color.b.max(0.0));
+
+        let mapped = match self.tone_mapping {
+            ToneMapping::None => color,
+            ToneMapping::Reinhard => Self::scale_luminance(color, |luminance| luminance / (1.0 + luminance)),
+            ToneMapping::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                Self::scale_luminance(color, |luminance| luminance * (1.0 + luminance / white_squared) / (1.0 + luminance))
+            },
+            ToneMapping::Aces => Self::aces(color),
+            ToneMapping::Agx => Self::agx(color),
+        };
+
+        Color::new(linear_to_srgb(mapped.r), linear_to_srgb(mapped.g), linear_to_srgb(mapped.b)).clamp()
+    }
+
+    // Maps the luminance and keeps the ratios between the channels.
+    fn scale_luminance(color: Color, map: impl Fn(f64) -> f64) -> Color {
+        let luminance = color.luminance();
+        if luminance <= 0.0 {
+            return color;
+        }
+
+        color * (map(luminance) / luminance)
+    }
+
+    fn aces(color: Color) -> Color {
+        const INPUT: [[f64; 3]; 3] = [
+            [0.59719, 0.35458, 0.04823],
+            [0.07600, 0.90834, 0.01566],
+            [0.02840, 0.13383, 0.83777],
+        ];
+        const OUTPUT: [[f64; 3]; 3] = [
+            [1.60475, -0.53108, -0.07367],
+            [-0.10208, 1.10813, -0.00605],
+            [-0.00327, -0.07276, 1.07602],
+        ];
+
+        let fit = |v: f64| (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081);
+
+        let color = multiply(&INPUT, color);
+        multiply(&OUTPUT, Color::new(fit(color.r), fit(color.g), fit(color.b)))
+    }
+
+    fn agx(color: Color) -> Color {
+        const INSET: [[f64; 3]; 3] = [
+            [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+            [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+            [0.0423756549057051, 0.0784336, 0.879142973793104],
+        ];
+        const OUTSET: [[f64; 3]; 3] = [
+            [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+            [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+            [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+        ];
+        const MIN_EV: f64 = -12.47393;
+        const MAX_EV: f64 = 4.026069;
+
+        // Log encoding followed by the sigmoid contrast curve.
+        let curve = |value: f64| {
+            let x = (value.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+            let (x2, x4) = (x * x, x * x * x * x);
+            15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+        };
+
+        let color = multiply(&INSET, color);
+        let color = multiply(&OUTSET, Color::new(curve(color.r), curve(color.g), curve(color.b)));
+
+        // The curve produces display encoded values, decode them so the sRGB encoding is not applied twice.
+        Color::new(color.r.max(0.0).powf(2.2), color.g.max(0.0).powf(2.2), color.b.max(0.0).powf(2.2))
+    }
+}
+
+fn multiply(matrix: &[[f64; 3]; 3], color: Color) -> Color {
+    let row = |row: &[f64; 3]| row[0] * color.r + row[1] * color.g + row[2] * color.b;
+    Color::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(tone_mapping: ToneMapping) -> DisplayTransform {
+        DisplayTransform { tone_mapping, ..DisplayTransform::default() }
+    }
+
+    fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    #[test]
+    fn curves_rise_from_black_and_stay_displayable() {
+        for tone_mapping in ToneMapping::ALL {
+            let display = display(tone_mapping);
+            let values = [0.0, 0.01, 0.05, 0.18, 0.5, 1.0, 2.0, 8.0, 64.0].map(|value| display.apply(gray(value)).r);
+
+            assert!(values[0] < 1e-3, "{}", tone_mapping.name());
+            assert!(values.windows(2).all(|pair| pair[1] >= pair[0]), "{}: {:?}", tone_mapping.name(), values);
+            assert!(values.iter().all(|value| (0.0..1.0).contains(value)), "{}: {:?}", tone_mapping.name(), values);
+        }
+    }
+
+    #[test]
+    fn exposure_scales_by_powers_of_two() {
+        let brighter = DisplayTransform { exposure: 1.0, ..DisplayTransform::default() };
+        assert!((brighter.apply(gray(0.1)).r - display(ToneMapping::None).apply(gray(0.2)).r).abs() < 1e-12);
+    }
+
+    #[test]
+    fn no_tone_mapping_only_encodes_and_clips() {
+        let display = display(ToneMapping::None);
+
+        assert!((display.apply(gray(0.5)).r - 0.735357).abs() < 1e-6);
+        assert_eq!(display.apply(gray(4.0)).r, 0.999);
+        assert_eq!(display.apply(Color::new(-1.0, 0.0, 0.0)).r, 0.0);
+    }
+
+    #[test]
+    fn reinhard_halves_unit_luminance() {
+        assert!((display(ToneMapping::Reinhard).apply(gray(1.0)).r - linear_to_srgb(0.5)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn extended_reinhard_maps_the_white_point_to_white() {
+        let display = display(ToneMapping::ExtendedReinhard);
+
+        assert_eq!(display.apply(gray(display.white_point)).r, 0.999);
+        assert!(display.apply(gray(0.9 * display.white_point)).r < 0.99);
+    }
+
+    #[test]
+    fn filmic_curves_keep_middle_gray_in_the_middle() {
+        for tone_mapping in [ToneMapping::Aces, ToneMapping::Agx] {
+            let middle = display(tone_mapping).apply(gray(0.18)).r;
+            assert!(middle > 0.3 && middle < 0.6, "{}: {}", tone_mapping.name(), middle);
+        }
+    }
+
+    #[test]
+    fn agx_desaturates_bright_colors() {
+        let color = display(ToneMapping::Agx).apply(Color::new(64.0, 0.0, 0.0));
+        assert!(color.g > 0.5 && color.b > 0.5, "{:?}", color);
+
+        // Reinhard keeps the hue, however bright the color.
+        assert_eq!(display(ToneMapping::Reinhard).apply(Color::new(64.0, 0.0, 0.0)).g, 0.0);
+    }
+
+    #[test]
+    fn next_cycles_through_every_operator() {
+        let next = ToneMapping::ALL.map(ToneMapping::next);
+        assert_eq!(next, [ToneMapping::Reinhard, ToneMapping::ExtendedReinhard, ToneMapping::Aces, ToneMapping::Agx, ToneMapping::None]);
+    }
+}
@@ -16,12 +16,25 @@ impl Translate {
 
         Translate { object, offset, bounding_box }
     }
+
+    // The same offset applied to another object.
+    pub fn with_object(&self, object: Hittable) -> Self {
+        Self::new(object, self.offset)
+    }
+
+    pub fn object(&self) -> &Hittable {
+        &self.object
+    }
+
+    pub fn object_mut(&mut self) -> &mut Hittable {
+        &mut self.object
+    }
 }
 
 impl HittableTrait for Translate {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         // Move the ray backwards by the offset
-        let offset_r = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
+        let offset_r = ray.transform(|point| point - self.offset, |direction| direction);
 
         // Determine where (if any) an intersection occurs along the offset ray
         if let Some(mut hit_object) = self.object.hit(offset_r, ray_t) {
@@ -37,4 +50,13 @@ impl HittableTrait for Translate {
     fn get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
+
+    // Moving the origin along with the object leaves directions, and densities over them, unchanged.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        self.object.random(origin - self.offset)
+    }
 }
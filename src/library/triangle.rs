@@ -1,44 +1,117 @@
+use std::sync::Arc;
+
 use nalgebra::Vector3;
 
 use crate::library::basic_lib::*;
-use crate::library::{hittable::HittableTrait, material::Material};
+use crate::library::{hittable::HittableTrait, material::Material, random, vector3::Vector3Extensions};
 
 #[derive(Debug, Clone)]
 pub struct Triangle {
     q: Vector3<f64>, // Lower-left corner
     u: Vector3<f64>, // first side
     v: Vector3<f64>, // second side
-    material: Material,
+    material: Arc<Material>,
+    material_id: usize,
     bounding_box: Aabb,
     normal: Vector3<f64>,
     d: f64,          // a constant
     w: Vector3<f64>,
+    area: f64,
+    vertex_normals: Option<[Vector3<f64>; 3]>,
+    vertex_uvs: Option<[(f64, f64); 3]>,
+    // Partial derivatives of the hit point with respect to the texture coordinates.
+    dpdu: Vector3<f64>,
+    dpdv: Vector3<f64>,
 }
 
 impl Triangle {
 
-    pub fn new(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>, material: Material) -> Self {
+    pub fn new(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>, material: impl Into<Arc<Material>>) -> Self {
+        Self::new_with_attributes(a, b, c, None, None, material)
+    }
+
+    // Triangle with optional per-vertex normals for smooth shading and texture coordinates for a, b and c.
+    // Without texture coordinates a, b and c are mapped to (0, 0), (1, 0) and (0, 1).
+    pub fn new_with_attributes(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>, normals: Option<[Vector3<f64>; 3]>,
+        uvs: Option<[(f64, f64); 3]>, material: impl Into<Arc<Material>>) -> Self {
         let q = a;
         let u = b - a;
-        let v = c - b;
+        let v = c - a;
 
         let n = u.cross(&v);
         let normal = n.normalize();
         let d = normal.dot(&q);
         let w = n / n.dot(&n);
 
+        let bounding_box = Aabb::new_from_box(Aabb::new_from_point(a, b), Aabb::new_from_point(c, c)).pad();
+        let (dpdu, dpdv) = Self::position_derivatives(u, v, uvs);
+
         Self {
             q, u, v,
-            material,
-            bounding_box: Aabb::new_from_point(q, q + u + v).pad(),
+            material: material.into(),
+            material_id: 0,
+            bounding_box,
             normal, d, w,
+            area: 0.5 * n.norm(),
+            vertex_normals: normals.map(|normals| normals.map(|normal| normal.normalize())),
+            vertex_uvs: uvs,
+            dpdu, dpdv,
+        }
+    }
+
+    // Partial derivatives of the position with respect to the texture coordinates for a triangle with
+    // the edges `b - a` and `c - a`. Without texture coordinates, or when they do not span an area,
+    // the edges themselves are used, matching the (alpha, beta) fallback of `texture_coordinates`.
+    pub fn position_derivatives(edge_b: Vector3<f64>, edge_c: Vector3<f64>, uvs: Option<[(f64, f64); 3]>) -> (Vector3<f64>, Vector3<f64>) {
+        if let Some([uv_a, uv_b, uv_c]) = uvs {
+            let (du_b, dv_b) = (uv_b.0 - uv_a.0, uv_b.1 - uv_a.1);
+            let (du_c, dv_c) = (uv_c.0 - uv_a.0, uv_c.1 - uv_a.1);
+
+            let determinant = du_b * dv_c - dv_b * du_c;
+            if determinant.abs() > 1e-12 {
+                let inverse = 1.0 / determinant;
+                return ((dv_c * edge_b - dv_b * edge_c) * inverse, (du_b * edge_c - du_c * edge_b) * inverse);
+            }
         }
+
+        (edge_b, edge_c)
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
     }
 
-    // Given the hit point in plane coordinates, return false if it is outside the
-    // primitive, otherwise set the hit record UV coordinates and return true.
+    // Index of the material in the scene's material table, handed to every hit.
+    pub fn set_material_id(&mut self, material_id: usize) {
+        self.material_id = material_id;
+    }
+
+    // The hit point is a + alpha * (b - a) + beta * (c - a), so the barycentric weights of
+    // a, b and c are (1 - alpha - beta, alpha, beta).
+    fn shading_normal(&self, alpha: f64, beta: f64) -> Vector3<f64> {
+        match self.vertex_normals {
+            Some([na, nb, nc]) => ((1.0 - alpha - beta) * na + alpha * nb + beta * nc).normalize(),
+            None => self.normal,
+        }
+    }
+
+    fn texture_coordinates(&self, alpha: f64, beta: f64) -> (f64, f64) {
+        match self.vertex_uvs {
+            Some([uv_a, uv_b, uv_c]) => {
+                let gamma = 1.0 - alpha - beta;
+                (
+                    gamma * uv_a.0 + alpha * uv_b.0 + beta * uv_c.0,
+                    gamma * uv_a.1 + alpha * uv_b.1 + beta * uv_c.1,
+                )
+            },
+            None => (alpha, beta),
+        }
+    }
+
+    // Given the hit point in plane coordinates, return None if it is outside the
+    // primitive, otherwise return the barycentric coordinates of b and c.
     fn is_interior(a: f64, b: f64) -> Option<(f64, f64)> {
-        if (a < 0.0) || (1.0 < a) || (b < 0.0) || (1.0 < b) || (b > a) {
+        if a < 0.0 || b < 0.0 || a + b > 1.0 {
             return None;
         }
 
@@ -47,7 +120,7 @@ impl Triangle {
 }
 
 impl HittableTrait for Triangle {
-    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject> {
+    fn hit(&self, ray: Ray, ray_t: Interval) -> Option<HitObject<'_>> {
         let denomenator = self.normal.dot(&ray.direction);
 
         // No hit if the ray is parallel to the plane.
@@ -67,10 +140,14 @@ impl HittableTrait for Triangle {
         let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
         let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
 
-        if let Some((u, v)) = Self::is_interior(alpha, beta) {
+        if let Some((alpha, beta)) = Self::is_interior(alpha, beta) {
             // Ray hits the 2D shape; set the rest of the hit record and return true.
             let hit_point = ray.calculate_hit_position(t);
-            let hit_object = HitObject::new(hit_point, ray, self.normal, self.material.clone(), t, u, v);
+            let (u, v) = self.texture_coordinates(alpha, beta);
+            let shading_normal = self.shading_normal(alpha, beta);
+            let hit_object = HitObject::new_with_shading_normal(hit_point, ray, self.normal, shading_normal, &self.material, t, u, v)
+                .with_differentials(&ray, self.dpdu, self.dpdv)
+                .with_material_id(self.material_id);
 
             Some(hit_object)
         } else {
@@ -81,4 +158,27 @@ impl HittableTrait for Triangle {
     fn get_bounding_box(&self) -> Aabb {
         self.bounding_box
     }
+
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        let Some(hit_object) = self.hit(Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        // Convert the uniform density over the area into a density over solid angle.
+        let distance_squared = hit_object.t * hit_object.t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length_squared().sqrt()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Vector3<f64>) -> Vector3<f64> {
+        // Fold points from the far half of the parallelogram back into the triangle.
+        let (mut alpha, mut beta) = random::sample_2d();
+        if alpha + beta > 1.0 {
+            (alpha, beta) = (1.0 - alpha, 1.0 - beta);
+        }
+
+        let p = self.q + alpha * self.u + beta * self.v;
+        p - origin
+    }
 }
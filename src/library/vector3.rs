@@ -1,6 +1,8 @@
 use nalgebra::Vector3;
 use rand::prelude::*;
 
+use crate::library::random;
+
 pub trait Vector3Extensions {
     fn near_zero(&self) -> bool;
     fn length_squared(&self) -> f64;
@@ -18,7 +20,7 @@ impl Vector3Extensions for Vector3<f64> {
     }
 
     fn random_float_range(range: std::ops::Range<f64>) -> Vector3<f64> {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
 
         let x: f64 = rng.gen_range(range.clone());
         let y: f64 = rng.gen_range(range.clone());
@@ -1,28 +1,86 @@
-mod library;
-
 extern crate minifb;
 
-use library::{render, color::Color, fps_counter::FpsCounter};
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 
-use library::constants::{WIDTH, HEIGHT}; 
+use raytracer::unstable::{check_input as input, cli, fps_counter::FpsCounter};
+use raytracer::{Color, Denoiser, DisplayTransform, Renderer};
 
 fn main() {
-    let mut window = Window::new("Renderer", WIDTH, HEIGHT, WindowOptions::default())
-    .expect("Unable to create window");
+    let options = match cli::parse_view_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}\n", message);
+            }
+            eprintln!("{}", cli::VIEW_USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let mut fps_counter = FpsCounter::start();
-    let mut frame_index = 1;
+    let scene = cli::load_scene(&options.scene, options.resolution).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    });
 
-    let mut buffer = vec![Color::new(0.0, 0.0, 0.0); WIDTH * HEIGHT];
+    let mut renderer = Renderer::new(scene);
+    renderer.set_seed(options.seed);
+    renderer.set_sampler(options.sampler);
+    renderer.set_filter(options.filter);
+    view(renderer, options.denoise, options.display);
+}
+
+fn view(mut renderer: Renderer, mut denoise: bool, mut display: DisplayTransform) {
+    let (width, height) = (renderer.scene().camera.image_width, renderer.scene().camera.image_height);
+    let mut window = Window::new(&window_title(&display), width, height, WindowOptions::default())
+    .expect("Unable to create window");
+
+    let mut fps_counter = FpsCounter::start(width);
+    let denoiser = Denoiser::default();
 
     // Event loop
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        let reset_accumulation;
-        (buffer, reset_accumulation) = render::render(&window, buffer);
+        if input::check_input(&window, renderer.scene_mut()) {
+            renderer.reset();
+        }
+
+        if window.is_key_pressed(Key::N, KeyRepeat::No) {
+            denoise = !denoise;
+        }
 
-        let clamped_buffer = render::get_clamped_buffer(&buffer, &mut fps_counter, &mut frame_index, reset_accumulation);
-        
-        window.update_with_buffer(&clamped_buffer, WIDTH, HEIGHT).unwrap();
+        // The display transform only changes how the accumulated image is shown, so it keeps the samples.
+        let previous_display = display;
+        if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) {
+            display.exposure -= 0.5;
+        }
+        if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) {
+            display.exposure += 0.5;
+        }
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            display.tone_mapping = display.tone_mapping.next();
+        }
+        if display != previous_display {
+            window.set_title(&window_title(&display));
+        }
+
+        // Accumulating the guides restarts the image, so they are only turned on once they are needed.
+        if denoise && renderer.aovs().is_empty() {
+            renderer.set_aovs(&Denoiser::GUIDES);
+        }
+
+        renderer.render_frame();
+
+        let mut framebuffer = renderer.framebuffer();
+        if denoise {
+            framebuffer = denoiser.denoise(&framebuffer).unwrap_or(framebuffer);
+        }
+
+        let mut pixels: Vec<u32> = framebuffer.to_display_colors(&display).map(Color::to_u32).collect();
+        fps_counter.update(&mut pixels);
+
+        window.update_with_buffer(&pixels, width, height).unwrap();
     }
 }
+
+fn window_title(display: &DisplayTransform) -> String {
+    format!("Renderer - exposure {:+.1} EV, tone mapping {}", display.exposure, display.tone_mapping.name())
+}
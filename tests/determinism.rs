@@ -0,0 +1,104 @@
+use raytracer::{Aov, Filter, FilterKind, Framebuffer, Renderer, Sampler, Scene};
+
+// Renders the smoky Cornell box, whose media draw random numbers during intersection, on a pool with
+// the given number of threads.
+fn render(threads: usize, seed: u64, sampler: Sampler) -> Framebuffer {
+    let mut scene = Scene::new(8);
+    scene.set_resolution(24, 1.0);
+
+    let mut renderer = Renderer::new(scene);
+    renderer.set_seed(seed);
+    renderer.set_sampler(sampler);
+    // A filter wider than a pixel splats samples across the bands that threads render separately.
+    renderer.set_filter(Filter::new(FilterKind::Mitchell));
+    renderer.set_aovs(&[Aov::Albedo, Aov::Depth]);
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    pool.install(|| renderer.render(2))
+}
+
+fn bits(framebuffer: &Framebuffer) -> Vec<[u64; 3]> {
+    let aov_pixels = framebuffer.aovs.iter().flat_map(|(_, pixels)| pixels.iter());
+    framebuffer.pixels.iter().chain(aov_pixels).map(|color| [color.r.to_bits(), color.g.to_bits(), color.b.to_bits()]).collect()
+}
+
+#[test]
+fn same_seed_gives_the_same_image_on_any_number_of_threads() {
+    for sampler in Sampler::ALL {
+        let single = render(1, 7, sampler);
+        let parallel = render(3, 7, sampler);
+
+        assert_eq!(bits(&single), bits(&parallel), "{} sampler", sampler.name());
+    }
+}
+
+#[test]
+fn different_seeds_give_different_images() {
+    assert_ne!(bits(&render(2, 1, Sampler::Sobol)), bits(&render(2, 2, Sampler::Sobol)));
+}
+
+// Three noise textures, each built from numbers drawn from the same generator.
+const NOISE_SCENE: &str = r#"
+[camera]
+image_width = 24
+lookfrom = [0.0, 0.0, 12.0]
+lookat = [0.0, 0.0, 0.0]
+
+[textures.fine]
+type = "perlin"
+scale = 8.0
+
+[textures.medium]
+type = "perlin"
+scale = 2.0
+
+[textures.coarse]
+type = "perlin"
+scale = 0.5
+
+[materials.fine]
+type = "lambertian"
+albedo = "fine"
+
+[materials.medium]
+type = "lambertian"
+albedo = "medium"
+
+[materials.coarse]
+type = "lambertian"
+albedo = "coarse"
+
+[[objects]]
+type = "sphere"
+center = [-2.5, 0.0, 0.0]
+radius = 1.2
+material = "fine"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.2
+material = "medium"
+
+[[objects]]
+type = "sphere"
+center = [2.5, 0.0, 0.0]
+radius = 1.2
+material = "coarse"
+"#;
+
+#[test]
+fn scene_files_with_noise_textures_load_the_same_every_time() {
+    let path = std::env::temp_dir().join(format!("raytracer-determinism-{}.toml", std::process::id()));
+    std::fs::write(&path, NOISE_SCENE).unwrap();
+
+    let images: Vec<_> = (0..4).map(|_| {
+        let mut renderer = Renderer::new(raytracer::load_scene_file(&path).unwrap());
+        bits(&renderer.render(1))
+    }).collect();
+    std::fs::remove_file(&path).unwrap();
+
+    for image in &images[1..] {
+        assert_eq!(&images[0], image);
+    }
+}